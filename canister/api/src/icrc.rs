@@ -0,0 +1,67 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use types::{CanisterId, TimestampNanos};
+
+pub type Subaccount = [u8; 32];
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Subaccount>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NotifyIcrcTransactionArgs {
+    pub token_symbol: String,
+    pub ledger_canister_id: CanisterId,
+    pub block_index: u64,
+    pub transaction: IcrcTransaction,
+    // Increases by 1 for each notification sent to a given subscriber
+    #[serde(default)]
+    pub notification_id: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IcrcTransaction {
+    pub operation: IcrcOperation,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<TimestampNanos>,
+    pub timestamp: TimestampNanos,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum IcrcOperation {
+    Mint {
+        to: Account,
+        amount: u128,
+    },
+    Burn {
+        from: Account,
+        spender: Option<Account>,
+        amount: u128,
+    },
+    Transfer {
+        from: Account,
+        to: Account,
+        amount: u128,
+        fee: Option<u128>,
+    },
+    TransferFrom {
+        from: Account,
+        to: Account,
+        spender: Account,
+        amount: u128,
+        fee: Option<u128>,
+    },
+    Approve {
+        from: Account,
+        spender: Account,
+        amount: u128,
+        expected_allowance: Option<u128>,
+        expires_at: Option<TimestampNanos>,
+        fee: Option<u128>,
+    },
+}
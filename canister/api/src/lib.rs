@@ -1,12 +1,14 @@
-use candid::CandidType;
-use ic_ledger_types::{Block, BlockIndex};
+use candid::{CandidType, Principal};
+use ic_ledger_types::{AccountIdentifier, Block, BlockIndex};
 use serde::{Deserialize, Serialize};
-use types::CanisterId;
+use types::{CanisterId, Cycles, Milliseconds, TimestampMillis, TimestampNanos};
 
+mod icrc;
 mod lifecycle;
 mod queries;
 mod updates;
 
+pub use icrc::*;
 pub use lifecycle::*;
 pub use queries::*;
 pub use updates::*;
@@ -17,4 +19,202 @@ pub struct NotifyTransactionArgs {
     pub ledger_canister_id: CanisterId,
     pub block_index: BlockIndex,
     pub block: Block,
+    // Increases by 1 for each notification sent to a given subscriber
+    #[serde(default)]
+    pub notification_id: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerStandard {
+    // The legacy ICP ledger interface (`query_blocks`) with accounts given as `AccountIdentifier`s
+    Icp,
+    // ICRC-1 ledgers which expose the `get_transactions` endpoint
+    Icrc1,
+    // ICRC-3 ledgers which expose the `icrc3_get_blocks` endpoint
+    Icrc3,
+}
+
+impl Default for LedgerStandard {
+    fn default() -> Self {
+        LedgerStandard::Icp
+    }
+}
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum SubscriptionAccount {
+    AccountIdentifier(AccountIdentifier),
+    Icrc(Account),
+}
+
+impl From<AccountIdentifier> for SubscriptionAccount {
+    fn from(account_identifier: AccountIdentifier) -> Self {
+        SubscriptionAccount::AccountIdentifier(account_identifier)
+    }
+}
+
+impl From<Account> for SubscriptionAccount {
+    fn from(account: Account) -> Self {
+        SubscriptionAccount::Icrc(account)
+    }
+}
+
+// Restricts which transactions a subscribed canister is notified of. Each field which is set must
+// be satisfied for a notification to be sent.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotificationFilter {
+    pub direction: Option<Direction>,
+    pub operation_kinds: Option<Vec<OperationKind>>,
+    pub min_amount: Option<u128>,
+    pub token_symbol: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationKind {
+    Transfer,
+    Mint,
+    Burn,
+    Approve,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum NotificationArgs {
+    Icp(NotifyTransactionArgs),
+    Icrc(NotifyIcrcTransactionArgs),
+}
+
+impl NotificationArgs {
+    pub fn ledger_canister_id(&self) -> CanisterId {
+        match self {
+            NotificationArgs::Icp(a) => a.ledger_canister_id,
+            NotificationArgs::Icrc(a) => a.ledger_canister_id,
+        }
+    }
+
+    pub fn block_index(&self) -> BlockIndex {
+        match self {
+            NotificationArgs::Icp(a) => a.block_index,
+            NotificationArgs::Icrc(a) => a.block_index,
+        }
+    }
+
+    pub fn timestamp_nanos(&self) -> TimestampNanos {
+        match self {
+            NotificationArgs::Icp(a) => a.block.timestamp.timestamp_nanos,
+            NotificationArgs::Icrc(a) => a.transaction.timestamp,
+        }
+    }
+
+    pub fn notification_id(&self) -> u64 {
+        match self {
+            NotificationArgs::Icp(a) => a.notification_id,
+            NotificationArgs::Icrc(a) => a.notification_id,
+        }
+    }
+
+    pub fn set_notification_id(&mut self, notification_id: u64) {
+        match self {
+            NotificationArgs::Icp(a) => a.notification_id = notification_id,
+            NotificationArgs::Icrc(a) => a.notification_id = notification_id,
+        }
+    }
+}
+
+// Push mode (the default) calls the subscriber's notification method for each notification. In pull
+// mode notifications are retained until the subscriber fetches and acknowledges them by calling
+// `get_notifications`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryMode {
+    Push,
+    Pull,
+}
+
+// Roles which can be granted to principals in addition to the admins, who implicitly hold every role.
+// Token operators can add, update and remove tokens. Subscription managers can manage the
+// subscriptions of any canister. Observers can read the subscriptions of any canister and the dead
+// letters. The admins act as super-admins: they implicitly hold every role and only they can manage
+// the admins and grant or revoke roles.
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub enum Role {
+    TokenOperator,
+    SubscriptionManager,
+    Observer,
+}
+
+// A privileged call made to the canister. The args and response are recorded in their debug format.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AuditLogEntry {
+    pub id: u64,
+    pub timestamp: TimestampMillis,
+    pub caller: Principal,
+    pub endpoint: String,
+    pub args: String,
+    pub response: String,
+}
+
+// Statistics about the notifications pushed to a subscriber. Latency is measured from the time of the
+// block to the time the notification was successfully pushed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SubscriberStats {
+    pub canister_id: CanisterId,
+    pub delivered: u64,
+    pub failed: u64,
+    pub retried: u64,
+    pub dead_lettered: u64,
+    pub last_success: Option<TimestampMillis>,
+    pub last_failure: Option<TimestampMillis>,
+    pub last_error_code: Option<i32>,
+    pub last_error_message: Option<String>,
+    pub average_latency_ms: Option<u64>,
+}
+
+// The limits beyond which the `/health` route reports the canister as unhealthy. Each check is
+// skipped if its threshold is not set. The sync checks only apply to tokens with syncing enabled.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HealthThresholds {
+    pub max_blocks_behind: Option<u64>,
+    pub max_seconds_behind: Option<u64>,
+    pub max_notification_queue_length: Option<u64>,
+    pub min_cycles_balance: Option<Cycles>,
+    pub max_time_since_last_successful_sync: Option<Milliseconds>,
+}
+
+// A message recorded by the canister's logger, serialized as JSON
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LogMessage {
+    pub timestamp: TimestampMillis,
+    pub json: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub canister_id: CanisterId,
+    pub args: NotificationArgs,
+    pub attempts: u32,
+    pub failed_at: TimestampMillis,
+    pub last_error: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DeadLetterFilter {
+    pub canister_id: Option<CanisterId>,
+    pub ledger_canister_id: Option<CanisterId>,
+    pub from_block_index: Option<BlockIndex>,
+    pub to_block_index: Option<BlockIndex>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DeadLetterSelection {
+    Ids(Vec<u64>),
+    Filter(DeadLetterFilter),
 }
@@ -6,6 +6,8 @@ use types::Version;
 pub struct Args {
     pub admins: Vec<Principal>,
     pub notification_method_name: Option<String>,
+    pub icrc_notification_method_name: Option<String>,
+    pub max_notification_attempts: Option<u32>,
     pub wasm_version: Version,
     pub test_mode: bool,
 }
@@ -5,4 +5,5 @@ use types::Version;
 #[derive(CandidType, Deserialize, Debug)]
 pub struct Args {
     pub wasm_version: Version,
+    pub max_notification_attempts: Option<u32>,
 }
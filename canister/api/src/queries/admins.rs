@@ -0,0 +1,10 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(Vec<Principal>),
+}
@@ -0,0 +1,20 @@
+use crate::AuditLogEntry;
+use candid::CandidType;
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub after_id: Option<u64>,
+    pub max_results: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(SuccessResult),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SuccessResult {
+    pub entries: Vec<AuditLogEntry>,
+    pub total: u64,
+}
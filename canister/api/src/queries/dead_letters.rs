@@ -0,0 +1,15 @@
+use crate::{DeadLetter, DeadLetterFilter};
+use candid::CandidType;
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub filter: DeadLetterFilter,
+    pub after_id: Option<u64>,
+    pub max_results: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(Vec<DeadLetter>),
+}
@@ -0,0 +1,14 @@
+use crate::LogMessage;
+use candid::CandidType;
+use serde::Deserialize;
+use types::TimestampMillis;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub since: Option<TimestampMillis>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(Vec<LogMessage>),
+}
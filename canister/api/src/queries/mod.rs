@@ -1 +1,9 @@
+pub mod admins;
+pub mod audit_log;
+pub mod dead_letters;
+pub mod logs;
+pub mod roles;
+pub mod subscriber_stats;
+pub mod subscriptions;
 pub mod supported_tokens;
+pub mod traces;
@@ -0,0 +1,15 @@
+use crate::SubscriberStats;
+use candid::CandidType;
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    // If empty, the stats of all subscribers are returned
+    pub canister_ids: Vec<CanisterId>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(Vec<SubscriberStats>),
+}
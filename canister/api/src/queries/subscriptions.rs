@@ -0,0 +1,38 @@
+use crate::{NotificationFilter, SubscriptionAccount};
+use candid::CandidType;
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub query: SubscriptionsQuery,
+    pub skip: u32,
+    pub max_results: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum SubscriptionsQuery {
+    // The canisters subscribed to the given account
+    Account(SubscriptionAccount),
+    // The accounts the given canister is subscribed to
+    Canister(CanisterId),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(SuccessResult),
+    NotAuthorized,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SuccessResult {
+    pub subscriptions: Vec<SubscriptionDetails>,
+    pub total: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SubscriptionDetails {
+    pub account: SubscriptionAccount,
+    pub canister_id: CanisterId,
+    pub filter: Option<NotificationFilter>,
+}
@@ -1,3 +1,4 @@
+use crate::LedgerStandard;
 use candid::CandidType;
 use ic_ledger_types::BlockIndex;
 use serde::Deserialize;
@@ -8,6 +9,7 @@ pub struct Args {
     pub ledger_canister_id: CanisterId,
     pub enable_sync: bool,
     pub sync_from_block_index: Option<BlockIndex>,
+    pub ledger_standard: Option<LedgerStandard>,
 }
 
 #[derive(CandidType, Deserialize, Debug)]
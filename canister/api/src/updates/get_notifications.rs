@@ -0,0 +1,21 @@
+use crate::NotificationArgs;
+use candid::CandidType;
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    // All notifications up to and including this id are acknowledged and will not be returned again
+    pub since_id: Option<u64>,
+    pub max_results: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(SuccessResult),
+    PullModeNotEnabled,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SuccessResult {
+    pub notifications: Vec<NotificationArgs>,
+}
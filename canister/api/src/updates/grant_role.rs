@@ -0,0 +1,15 @@
+use crate::Role;
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success,
+    AlreadyGranted,
+}
@@ -1,3 +1,15 @@
+pub mod add_admin;
 pub mod add_token;
+pub mod get_notifications;
+pub mod grant_role;
+pub mod purge_dead_letters;
+pub mod remove_admin;
+pub mod remove_token;
+pub mod replay_dead_letters;
+pub mod revoke_role;
+pub mod set_delivery_mode;
+pub mod set_health_thresholds;
 pub mod subscribe;
+pub mod unsubscribe;
+pub mod update_subscriber_allowlist;
 pub mod update_token_config;
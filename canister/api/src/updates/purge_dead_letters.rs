@@ -0,0 +1,13 @@
+use crate::DeadLetterSelection;
+use candid::CandidType;
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub selection: DeadLetterSelection,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(u32),
+}
@@ -0,0 +1,18 @@
+use candid::CandidType;
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub ledger_canister_id: Option<CanisterId>,
+    pub token_symbol: Option<String>,
+    // If false, any notifications already queued for the token are still delivered
+    pub purge_pending_notifications: bool,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(u32),
+    TokenNotFound,
+    AmbiguousTokenSymbol(Vec<CanisterId>),
+}
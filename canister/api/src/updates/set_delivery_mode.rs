@@ -0,0 +1,16 @@
+use crate::DeliveryMode;
+use candid::CandidType;
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub canister_ids: Vec<CanisterId>,
+    pub mode: DeliveryMode,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success,
+    NotAuthorized(Vec<CanisterId>),
+}
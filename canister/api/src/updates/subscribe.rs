@@ -1,3 +1,4 @@
+use crate::{Account, NotificationFilter};
 use candid::CandidType;
 use ic_ledger_types::AccountIdentifier;
 use serde::Deserialize;
@@ -6,15 +7,25 @@ use types::CanisterId;
 #[derive(CandidType, Deserialize, Debug)]
 pub struct Args {
     pub subscriptions: Vec<Subscription>,
+    pub icrc_subscriptions: Option<Vec<IcrcSubscription>>,
 }
 
 #[derive(CandidType, Deserialize, Debug)]
 pub enum Response {
     Success,
+    NotAuthorized(Vec<CanisterId>),
 }
 
-#[derive(CandidType, Deserialize, Debug)]
+#[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct Subscription {
     pub account_identifier: AccountIdentifier,
     pub canister_ids: Vec<CanisterId>,
+    pub filter: Option<NotificationFilter>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IcrcSubscription {
+    pub account: Account,
+    pub canister_ids: Vec<CanisterId>,
+    pub filter: Option<NotificationFilter>,
 }
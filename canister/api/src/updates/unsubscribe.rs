@@ -0,0 +1,35 @@
+use crate::Account;
+use candid::CandidType;
+use ic_ledger_types::AccountIdentifier;
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub subscriptions: Vec<Unsubscription>,
+    pub icrc_subscriptions: Option<Vec<IcrcUnsubscription>>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success(SuccessResult),
+    NotAuthorized(Vec<CanisterId>),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SuccessResult {
+    pub removed: Vec<Unsubscription>,
+    pub icrc_removed: Vec<IcrcUnsubscription>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Unsubscription {
+    pub account_identifier: AccountIdentifier,
+    pub canister_ids: Vec<CanisterId>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IcrcUnsubscription {
+    pub account: Account,
+    pub canister_ids: Vec<CanisterId>,
+}
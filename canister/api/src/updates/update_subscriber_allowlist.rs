@@ -0,0 +1,15 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+use types::CanisterId;
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct Args {
+    pub principal: Principal,
+    pub add: Vec<CanisterId>,
+    pub remove: Vec<CanisterId>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum Response {
+    Success,
+}
@@ -1,16 +1,21 @@
 use candid::CandidType;
 use ic_ledger_types::BlockIndex;
 use serde::Deserialize;
+use types::{CanisterId, Milliseconds};
 
 #[derive(CandidType, Deserialize, Debug)]
 pub struct Args {
-    pub token_symbol: String,
+    // The token is identified by its ledger, or by its symbol if that is unique
+    pub ledger_canister_id: Option<CanisterId>,
+    pub token_symbol: Option<String>,
     pub sync_enabled: Option<bool>,
     pub sync_from_block_index: Option<BlockIndex>,
+    pub sync_interval: Option<Milliseconds>,
 }
 
 #[derive(CandidType, Deserialize, Debug)]
 pub enum Response {
     Success,
     TokenNotFound,
+    AmbiguousTokenSymbol(Vec<CanisterId>),
 }
@@ -16,9 +16,29 @@ macro_rules! generate_c2c_call {
 }
 
 // Queries
+generate_c2c_call!(admins);
+generate_c2c_call!(audit_log);
+generate_c2c_call!(dead_letters);
+generate_c2c_call!(logs);
+generate_c2c_call!(roles);
+generate_c2c_call!(subscriber_stats);
+generate_c2c_call!(subscriptions);
 generate_c2c_call!(supported_tokens);
+generate_c2c_call!(traces);
 
 // Updates
+generate_c2c_call!(add_admin);
 generate_c2c_call!(add_token);
+generate_c2c_call!(get_notifications);
+generate_c2c_call!(grant_role);
+generate_c2c_call!(purge_dead_letters);
+generate_c2c_call!(remove_admin);
+generate_c2c_call!(remove_token);
+generate_c2c_call!(replay_dead_letters);
+generate_c2c_call!(revoke_role);
+generate_c2c_call!(set_delivery_mode);
+generate_c2c_call!(set_health_thresholds);
 generate_c2c_call!(subscribe);
+generate_c2c_call!(unsubscribe);
+generate_c2c_call!(update_subscriber_allowlist);
 generate_c2c_call!(update_token_config);
@@ -0,0 +1,80 @@
+use crate::read_state;
+use candid::{CandidType, Principal};
+use ic_cdk::api::call::CallResult;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::error;
+use transaction_notifier::Role;
+use types::CanisterId;
+
+// Returns the canister ids (if any) which the caller is not permitted to manage subscriptions for.
+// Subscription managers may manage subscriptions for any canister, otherwise the caller must either
+// be the canister itself, have been granted access via the subscriber allowlist, or be one of its
+// controllers.
+pub async fn canisters_caller_cannot_manage(canister_ids: Vec<CanisterId>) -> Vec<CanisterId> {
+    let (caller, canisters_to_check) = read_state(|state| {
+        let caller = state.env.caller();
+
+        let canisters_to_check: Vec<_> = if state.data.has_role(&caller, Role::SubscriptionManager)
+        {
+            Vec::new()
+        } else {
+            canister_ids
+                .into_iter()
+                .unique()
+                .filter(|c| *c != caller && !state.data.subscriber_allowlist.is_allowed(&caller, c))
+                .collect()
+        };
+
+        (caller, canisters_to_check)
+    });
+
+    if canisters_to_check.is_empty() {
+        return Vec::new();
+    }
+
+    let futures: Vec<_> = canisters_to_check
+        .into_iter()
+        .map(|canister_id| is_controller(caller, canister_id))
+        .collect();
+
+    futures::future::join_all(futures)
+        .await
+        .into_iter()
+        .filter(|(_, is_controller)| !is_controller)
+        .map(|(canister_id, _)| canister_id)
+        .collect()
+}
+
+async fn is_controller(principal: Principal, canister_id: CanisterId) -> (CanisterId, bool) {
+    match canister_controllers(canister_id).await {
+        Ok(controllers) => (canister_id, controllers.contains(&principal)),
+        Err(error) => {
+            error!(%canister_id, ?error, "Failed to get canister controllers");
+            (canister_id, false)
+        }
+    }
+}
+
+async fn canister_controllers(canister_id: CanisterId) -> CallResult<Vec<Principal>> {
+    let args = CanisterInfoArgs {
+        canister_id,
+        num_requested_changes: None,
+    };
+
+    let (response,): (CanisterInfoResponse,) =
+        ic_cdk::call(Principal::management_canister(), "canister_info", (args,)).await?;
+
+    Ok(response.controllers)
+}
+
+#[derive(CandidType, Deserialize)]
+struct CanisterInfoArgs {
+    canister_id: CanisterId,
+    num_requested_changes: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct CanisterInfoResponse {
+    controllers: Vec<Principal>,
+}
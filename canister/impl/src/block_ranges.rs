@@ -0,0 +1,65 @@
+use ic_cdk::api::call::{CallResult, RejectionCode};
+
+// Joins ranges of blocks, each given as the index of its first block along with the blocks, into
+// a single list starting at `start`. The ranges must be ordered by index. Callers derive each
+// block's index from its position in the list, so any gap or overlap is returned as an error.
+pub fn join_contiguous<T>(
+    start: u64,
+    ranges: impl IntoIterator<Item = (u64, Vec<T>)>,
+) -> CallResult<Vec<T>> {
+    let mut blocks = Vec::new();
+    let mut next_block_index = start;
+
+    for (first_block_index, range) in ranges {
+        if range.is_empty() {
+            continue;
+        }
+        if first_block_index != next_block_index {
+            return Err((
+                RejectionCode::CanisterError,
+                format!(
+                    "Blocks are not contiguous. Expected: {}. Received: {}",
+                    next_block_index, first_block_index
+                ),
+            ));
+        }
+        next_block_index += range.len() as u64;
+        blocks.extend(range);
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contiguous_ranges_are_joined() {
+        let ranges = vec![(10, vec![10, 11]), (12, vec![]), (12, vec![12, 13, 14])];
+
+        assert_eq!(
+            join_contiguous(10, ranges).unwrap(),
+            vec![10, 11, 12, 13, 14]
+        );
+    }
+
+    #[test]
+    fn gap_is_an_error() {
+        let ranges = vec![(10, vec![10, 11]), (13, vec![13])];
+
+        assert!(join_contiguous(10, ranges).is_err());
+    }
+
+    #[test]
+    fn overlap_is_an_error() {
+        let ranges = vec![(10, vec![10, 11]), (11, vec![11, 12])];
+
+        assert!(join_contiguous(10, ranges).is_err());
+    }
+
+    #[test]
+    fn range_not_starting_at_start_is_an_error() {
+        assert!(join_contiguous(10, vec![(11, vec![11])]).is_err());
+    }
+}
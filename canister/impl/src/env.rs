@@ -1,7 +1,7 @@
 use candid::Principal;
 use types::{CanisterId, Cycles, TimestampMillis};
 
-const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const NANOS_PER_MILLISECOND: u64 = 1_000_000;
 
 pub trait Environment {
     fn now(&self) -> TimestampMillis;
@@ -14,8 +14,10 @@ pub trait Environment {
 pub struct CanisterEnv {}
 
 impl Environment for CanisterEnv {
+    // Versions prior to the retry support returned seconds here, despite the return type. Any
+    // timestamps they persisted are converted on upgrade, see `migrate_timestamps_to_millis`.
     fn now(&self) -> TimestampMillis {
-        ic_cdk::api::time() / NANOS_PER_SECOND
+        ic_cdk::api::time() / NANOS_PER_MILLISECOND
     }
 
     fn caller(&self) -> Principal {
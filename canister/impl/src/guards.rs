@@ -1,4 +1,5 @@
 use crate::read_state;
+use transaction_notifier::Role;
 
 pub fn caller_is_admin() -> Result<(), String> {
     read_state(|state| {
@@ -10,3 +11,22 @@ pub fn caller_is_admin() -> Result<(), String> {
         }
     })
 }
+
+pub fn caller_is_token_operator() -> Result<(), String> {
+    caller_has_role(Role::TokenOperator, "Caller is not a token operator")
+}
+
+pub fn caller_is_observer() -> Result<(), String> {
+    caller_has_role(Role::Observer, "Caller is not an observer")
+}
+
+fn caller_has_role(role: Role, error: &str) -> Result<(), String> {
+    read_state(|state| {
+        let caller = state.env.caller();
+        if state.data.has_role(&caller, role) {
+            Ok(())
+        } else {
+            Err(error.to_string())
+        }
+    })
+}
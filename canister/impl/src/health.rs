@@ -0,0 +1,175 @@
+use crate::Metrics;
+use serde::Serialize;
+use transaction_notifier::HealthThresholds;
+
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub problems: Vec<String>,
+    pub thresholds: HealthThresholds,
+}
+
+pub fn check(metrics: &Metrics, thresholds: &HealthThresholds) -> HealthReport {
+    let mut problems = Vec::new();
+
+    if let Some(min) = thresholds.min_cycles_balance {
+        if metrics.cycles_balance < min {
+            problems.push(format!(
+                "Cycles balance is {} (min {min})",
+                metrics.cycles_balance
+            ));
+        }
+    }
+
+    if let Some(max) = thresholds.max_notification_queue_length {
+        if metrics.notifications_queued > max {
+            problems.push(format!(
+                "Notification queue length is {} (max {max})",
+                metrics.notifications_queued
+            ));
+        }
+    }
+
+    for token in metrics.tokens.iter().filter(|t| t.sync_enabled) {
+        let token_name = format!("{} ({})", token.token_symbol, token.ledger_canister_id);
+
+        if let Some(block_index) = token.hash_chain_mismatch {
+            problems.push(format!(
+                "{token_name}: sync halted due to hash chain mismatch at block {block_index}"
+            ));
+        }
+        if let (Some(max), Some(blocks_behind)) =
+            (thresholds.max_blocks_behind, token.blocks_behind)
+        {
+            if blocks_behind > max {
+                problems.push(format!(
+                    "{token_name}: {blocks_behind} blocks behind (max {max})"
+                ));
+            }
+        }
+        if let (Some(max), Some(seconds_behind)) =
+            (thresholds.max_seconds_behind, token.seconds_behind)
+        {
+            if seconds_behind > max {
+                problems.push(format!(
+                    "{token_name}: {seconds_behind} seconds behind (max {max})"
+                ));
+            }
+        }
+        // Tokens which have yet to attempt a sync are skipped
+        if let Some(max) = thresholds.max_time_since_last_successful_sync {
+            let attempted = token.last_successful_sync > 0 || token.last_failed_sync > 0;
+            let elapsed = metrics.now.saturating_sub(token.last_successful_sync);
+            if attempted && elapsed > max {
+                problems.push(format!(
+                    "{token_name}: last successful sync was {elapsed}ms ago (max {max}ms)"
+                ));
+            }
+        }
+    }
+
+    HealthReport {
+        healthy: problems.is_empty(),
+        problems,
+        thresholds: thresholds.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenMetrics;
+
+    #[test]
+    fn healthy_if_no_thresholds_are_set() {
+        let metrics = Metrics {
+            tokens: vec![token(true)],
+            ..Default::default()
+        };
+
+        let report = check(&metrics, &HealthThresholds::default());
+
+        assert!(report.healthy);
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn each_exceeded_threshold_is_reported() {
+        let metrics = Metrics {
+            now: 100_000,
+            cycles_balance: 100,
+            notifications_queued: 20,
+            tokens: vec![token(true)],
+            ..Default::default()
+        };
+        let thresholds = HealthThresholds {
+            max_blocks_behind: Some(5),
+            max_seconds_behind: Some(30),
+            max_notification_queue_length: Some(10),
+            min_cycles_balance: Some(1000),
+            max_time_since_last_successful_sync: Some(60_000),
+        };
+
+        let report = check(&metrics, &thresholds);
+
+        assert!(!report.healthy);
+        assert_eq!(report.problems.len(), 6);
+        assert!(report.problems[0].starts_with("Cycles balance is 100"));
+        assert!(report.problems[1].starts_with("Notification queue length is 20"));
+        assert!(report.problems[2].contains("hash chain mismatch at block 7"));
+        assert!(report.problems[3].contains("10 blocks behind"));
+        assert!(report.problems[4].contains("60 seconds behind"));
+        assert!(report.problems[5].contains("last successful sync was 90000ms ago"));
+    }
+
+    #[test]
+    fn tokens_with_sync_disabled_are_skipped() {
+        let metrics = Metrics {
+            now: 100_000,
+            tokens: vec![token(false)],
+            ..Default::default()
+        };
+        let thresholds = HealthThresholds {
+            max_blocks_behind: Some(5),
+            max_seconds_behind: Some(30),
+            max_time_since_last_successful_sync: Some(60_000),
+            ..Default::default()
+        };
+
+        assert!(check(&metrics, &thresholds).healthy);
+    }
+
+    #[test]
+    fn tokens_yet_to_attempt_a_sync_are_not_reported_as_stale() {
+        let mut token = token(true);
+        token.last_successful_sync = 0;
+        token.last_failed_sync = 0;
+        token.hash_chain_mismatch = None;
+        let metrics = Metrics {
+            now: 100_000,
+            tokens: vec![token],
+            ..Default::default()
+        };
+        let thresholds = HealthThresholds {
+            max_time_since_last_successful_sync: Some(60_000),
+            ..Default::default()
+        };
+
+        assert!(check(&metrics, &thresholds).healthy);
+    }
+
+    fn token(sync_enabled: bool) -> TokenMetrics {
+        TokenMetrics {
+            sync_enabled,
+            synced_up_to: Some(89),
+            last_sync_started_at: 10_000,
+            last_successful_sync: 10_000,
+            hash_chain_mismatch: Some(7),
+            chain_length: Some(100),
+            last_block_timestamp: Some(40_000),
+            blocks_behind: Some(10),
+            seconds_behind: Some(60),
+            ..TokenMetrics::test_default("ICP")
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::icp_ledger::IcpBlock;
+use ic_ledger_types::{AccountIdentifier, Operation, Timestamp, Tokens};
+use sha2::{Digest, Sha256};
+
+pub type BlockHash = [u8; 32];
+
+// Computes the hashes the block may have, in the same way as the ICP ledger, which hashes the
+// protobuf encoding of the block. The encoding is written out by hand since only a handful of
+// message types are needed. Fields holding default values are omitted, as per proto3.
+//
+// A block's `created_at_time` is optional, but the ledger's candid interface fills it in with the
+// block's timestamp when it is missing. So if the two are equal it is unknown whether the field is
+// present in the encoded block, and the hashes with and without it are both returned.
+pub fn possible_hashes(block: &IcpBlock) -> Vec<BlockHash> {
+    let mut hashes = vec![hash_block(block, true)];
+    if block.block.transaction.created_at_time == block.block.timestamp {
+        hashes.push(hash_block(block, false));
+    }
+    hashes
+}
+
+fn hash_block(block: &IcpBlock, include_created_at_time: bool) -> BlockHash {
+    Sha256::digest(&encode_block(block, include_created_at_time)).into()
+}
+
+fn encode_block(block: &IcpBlock, include_created_at_time: bool) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    if let Some(parent_hash) = &block.block.parent_hash {
+        let mut hash = Vec::new();
+        write_bytes(&mut hash, 1, parent_hash);
+        write_message(&mut buffer, 1, &hash);
+    }
+    write_message(&mut buffer, 2, &encode_timestamp(&block.block.timestamp));
+    write_message(
+        &mut buffer,
+        3,
+        &encode_transaction(block, include_created_at_time),
+    );
+    buffer
+}
+
+fn encode_transaction(block: &IcpBlock, include_created_at_time: bool) -> Vec<u8> {
+    let transaction = &block.block.transaction;
+    let mut buffer = Vec::new();
+    match &transaction.operation {
+        Some(Operation::Burn { from, amount }) => {
+            let mut burn = Vec::new();
+            write_message(&mut burn, 1, &encode_account_identifier(from));
+            write_message(&mut burn, 3, &encode_tokens(amount));
+            write_message(&mut buffer, 1, &burn);
+        }
+        Some(Operation::Mint { to, amount }) => {
+            let mut mint = Vec::new();
+            write_message(&mut mint, 2, &encode_account_identifier(to));
+            write_message(&mut mint, 3, &encode_tokens(amount));
+            write_message(&mut buffer, 2, &mint);
+        }
+        Some(Operation::Transfer {
+            from,
+            to,
+            amount,
+            fee,
+        }) => {
+            let mut send = Vec::new();
+            write_message(&mut send, 1, &encode_account_identifier(from));
+            write_message(&mut send, 2, &encode_account_identifier(to));
+            write_message(&mut send, 3, &encode_tokens(amount));
+            write_message(&mut send, 4, &encode_tokens(fee));
+            write_message(&mut buffer, 3, &send);
+        }
+        None => {}
+    }
+
+    let mut memo = Vec::new();
+    write_u64(&mut memo, 1, transaction.memo.0);
+    write_message(&mut buffer, 4, &memo);
+    if include_created_at_time {
+        write_message(
+            &mut buffer,
+            6,
+            &encode_timestamp(&transaction.created_at_time),
+        );
+    }
+    if let Some(icrc1_memo) = &block.icrc1_memo {
+        let mut memo = Vec::new();
+        write_bytes(&mut memo, 1, icrc1_memo);
+        write_message(&mut buffer, 7, &memo);
+    }
+    buffer
+}
+
+fn encode_account_identifier(account_identifier: &AccountIdentifier) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_bytes(&mut buffer, 1, account_identifier.as_ref());
+    buffer
+}
+
+fn encode_tokens(tokens: &Tokens) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_u64(&mut buffer, 1, tokens.e8s());
+    buffer
+}
+
+fn encode_timestamp(timestamp: &Timestamp) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_u64(&mut buffer, 1, timestamp.timestamp_nanos);
+    buffer
+}
+
+fn write_u64(buffer: &mut Vec<u8>, field_number: u64, value: u64) {
+    if value != 0 {
+        write_varint(buffer, field_number << 3);
+        write_varint(buffer, value);
+    }
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, field_number: u64, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        write_length_delimited(buffer, field_number, bytes);
+    }
+}
+
+// Nested messages are always written, even if empty
+fn write_message(buffer: &mut Vec<u8>, field_number: u64, message: &[u8]) {
+    write_length_delimited(buffer, field_number, message);
+}
+
+fn write_length_delimited(buffer: &mut Vec<u8>, field_number: u64, bytes: &[u8]) {
+    write_varint(buffer, (field_number << 3) | 2);
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_ledger_types::{Block, Memo, Transaction};
+    use serde_bytes::ByteBuf;
+
+    #[test]
+    fn block_is_encoded_as_protobuf() {
+        let block = block(1, 1, None);
+
+        assert_eq!(
+            encode_block(&block, true),
+            vec![0x12, 2, 0x08, 1, 0x1a, 6, 0x22, 0, 0x32, 2, 0x08, 1]
+        );
+        assert_eq!(
+            encode_block(&block, false),
+            vec![0x12, 2, 0x08, 1, 0x1a, 2, 0x22, 0]
+        );
+    }
+
+    #[test]
+    fn icrc1_memo_is_encoded_after_created_at_time() {
+        let block = block(1, 2, Some(vec![0xab]));
+
+        assert_eq!(
+            encode_block(&block, true),
+            vec![0x12, 2, 0x08, 1, 0x1a, 11, 0x22, 0, 0x32, 2, 0x08, 2, 0x3a, 3, 0x0a, 1, 0xab]
+        );
+        assert_ne!(
+            possible_hashes(&block),
+            possible_hashes(&self::block(1, 2, None))
+        );
+    }
+
+    #[test]
+    fn both_hashes_are_possible_if_created_at_time_matches_timestamp() {
+        assert_eq!(possible_hashes(&block(1, 2, None)).len(), 1);
+
+        let hashes = possible_hashes(&block(1, 1, None));
+        assert_eq!(hashes.len(), 2);
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
+    fn block(timestamp: u64, created_at_time: u64, icrc1_memo: Option<Vec<u8>>) -> IcpBlock {
+        IcpBlock {
+            block: Block {
+                parent_hash: None,
+                transaction: Transaction {
+                    memo: Memo(0),
+                    operation: None,
+                    created_at_time: Timestamp {
+                        timestamp_nanos: created_at_time,
+                    },
+                },
+                timestamp: Timestamp {
+                    timestamp_nanos: timestamp,
+                },
+            },
+            icrc1_memo: icrc1_memo.map(ByteBuf::from),
+        }
+    }
+}
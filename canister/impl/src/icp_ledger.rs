@@ -0,0 +1,149 @@
+use crate::block_ranges::join_contiguous;
+use candid::{CandidType, Func};
+use ic_cdk::api::call::{CallResult, RejectionCode};
+use ic_ledger_types::{
+    ArchivedBlockRange, Block, BlockIndex, GetBlocksArgs, GetBlocksError, Memo, Operation,
+    Timestamp, Transaction,
+};
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use std::iter;
+use types::CanisterId;
+
+// A block as returned by the ledger. `Block` has no field for the ICRC-1 memo, which is needed in
+// order to compute the block's hash, so it is held alongside.
+#[derive(Clone)]
+pub struct IcpBlock {
+    pub block: Block,
+    pub icrc1_memo: Option<ByteBuf>,
+}
+
+// Mirrors `ic_ledger_types::QueryBlocksResponse` but with blocks which include the ICRC-1 memo
+#[derive(CandidType, Deserialize)]
+struct QueryBlocksResponse {
+    chain_length: u64,
+    first_block_index: BlockIndex,
+    blocks: Vec<CandidBlock>,
+    archived_blocks: Vec<ArchivedBlockRange>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct BlockRange {
+    blocks: Vec<CandidBlock>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct CandidBlock {
+    parent_hash: Option<[u8; 32]>,
+    transaction: CandidTransaction,
+    timestamp: Timestamp,
+}
+
+#[derive(CandidType, Deserialize)]
+struct CandidTransaction {
+    memo: Memo,
+    icrc1_memo: Option<ByteBuf>,
+    operation: Option<Operation>,
+    created_at_time: Timestamp,
+}
+
+// Returns the blocks starting at `start`, including those held in archive canisters. Archives
+// may return fewer blocks than requested, in which case the remainder of each range is
+// requested again. The blocks are then checked to be contiguous so that none are skipped. The
+// ledger's chain length is returned alongside the blocks.
+pub async fn blocks_since(
+    ledger_canister_id: CanisterId,
+    start: BlockIndex,
+    length: u64,
+) -> CallResult<(Vec<IcpBlock>, u64)> {
+    let (response,): (QueryBlocksResponse,) = ic_cdk::call(
+        ledger_canister_id,
+        "query_blocks",
+        (GetBlocksArgs { start, length },),
+    )
+    .await?;
+
+    // Get the blocks from the archive canisters
+    let futures: Vec<_> = response
+        .archived_blocks
+        .into_iter()
+        .sorted_by_key(|a| a.start)
+        .map(|range| async move {
+            let range_start = range.start;
+            get_blocks_from_archive(range)
+                .await
+                .map(|blocks| (range_start, blocks))
+        })
+        .collect();
+
+    let archived_blocks = futures::future::join_all(futures)
+        .await
+        .into_iter()
+        .collect::<CallResult<Vec<_>>>()?;
+
+    let blocks = join_contiguous(
+        start,
+        archived_blocks
+            .into_iter()
+            .chain(iter::once((response.first_block_index, response.blocks))),
+    )?
+    .into_iter()
+    .map(IcpBlock::from)
+    .collect();
+
+    Ok((blocks, response.chain_length))
+}
+
+async fn get_blocks_from_archive(range: ArchivedBlockRange) -> CallResult<Vec<CandidBlock>> {
+    let func: Func = range.callback.into();
+    let end = range.start + range.length;
+
+    let mut blocks = Vec::new();
+    let mut start = range.start;
+
+    while start < end {
+        let args = GetBlocksArgs {
+            start,
+            length: end - start,
+        };
+        let (response,): (Result<BlockRange, GetBlocksError>,) =
+            ic_cdk::call(func.principal, &func.method, (args,)).await?;
+
+        let block_range = response.map_err(|error| {
+            (
+                RejectionCode::CanisterError,
+                format!("Archive returned an error: {error:?}"),
+            )
+        })?;
+
+        if block_range.blocks.is_empty() {
+            return Err((
+                RejectionCode::CanisterError,
+                format!("Archive returned no blocks. Start: {start}"),
+            ));
+        }
+
+        start += block_range.blocks.len() as u64;
+        blocks.extend(block_range.blocks);
+    }
+
+    Ok(blocks)
+}
+
+impl From<CandidBlock> for IcpBlock {
+    fn from(block: CandidBlock) -> Self {
+        IcpBlock {
+            block: Block {
+                parent_hash: block.parent_hash,
+                transaction: Transaction {
+                    memo: block.transaction.memo,
+                    operation: block.transaction.operation,
+                    created_at_time: block.transaction.created_at_time,
+                },
+                timestamp: block.timestamp,
+            },
+            icrc1_memo: block.transaction.icrc1_memo,
+        }
+    }
+}
@@ -0,0 +1,693 @@
+use crate::block_ranges::join_contiguous;
+use candid::{CandidType, Func, Int, Nat, Principal};
+use ic_cdk::api::call::{CallResult, RejectionCode};
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use std::iter;
+use transaction_notifier::{Account, IcrcOperation, IcrcTransaction, LedgerStandard};
+use types::{CanisterId, TimestampNanos};
+
+pub async fn token_symbol(ledger_canister_id: CanisterId) -> CallResult<String> {
+    let (symbol,): (String,) = ic_cdk::call(ledger_canister_id, "icrc1_symbol", ()).await?;
+    Ok(symbol)
+}
+
+pub async fn chain_length(
+    ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
+) -> CallResult<u64> {
+    let log_length = if ledger_standard == LedgerStandard::Icrc3 {
+        icrc3::get_blocks(ledger_canister_id, 0, 0)
+            .await?
+            .log_length
+    } else {
+        icrc1::get_transactions(ledger_canister_id, 0, 0)
+            .await?
+            .log_length
+    };
+
+    Ok(log_length as u64)
+}
+
+// Returns the transactions starting at `start`, including those held in archive canisters, along
+// with the ledger's chain length. The result may contain fewer than `length` transactions if the
+// end of the chain has been reached. Archives may return fewer transactions than requested, in
+// which case the remainder of each range is requested again, and the transactions are then checked
+// to be contiguous so that none are skipped.
+pub async fn transactions_since(
+    ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
+    start: u64,
+    length: u64,
+) -> CallResult<(Vec<IcrcTransaction>, u64)> {
+    if ledger_standard == LedgerStandard::Icrc3 {
+        icrc3::blocks_since(ledger_canister_id, start, length).await
+    } else {
+        icrc1::transactions_since(ledger_canister_id, start, length).await
+    }
+}
+
+fn decode_error(message: String) -> (RejectionCode, String) {
+    (RejectionCode::CanisterError, message)
+}
+
+mod icrc1 {
+    use super::*;
+
+    #[derive(CandidType, Deserialize)]
+    struct GetTransactionsRequest {
+        start: u128,
+        length: u128,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    pub struct GetTransactionsResponse {
+        pub log_length: u128,
+        first_index: u128,
+        pub transactions: Vec<Transaction>,
+        pub archived_transactions: Vec<ArchivedRange>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    pub struct ArchivedRange {
+        start: u128,
+        length: u128,
+        callback: Func,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct TransactionRange {
+        transactions: Vec<Transaction>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    pub struct Transaction {
+        kind: String,
+        mint: Option<Mint>,
+        burn: Option<Burn>,
+        transfer: Option<Transfer>,
+        approve: Option<Approve>,
+        timestamp: TimestampNanos,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct Mint {
+        to: Account,
+        amount: u128,
+        memo: Option<ByteBuf>,
+        created_at_time: Option<TimestampNanos>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct Burn {
+        from: Account,
+        spender: Option<Account>,
+        amount: u128,
+        memo: Option<ByteBuf>,
+        created_at_time: Option<TimestampNanos>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct Transfer {
+        from: Account,
+        to: Account,
+        spender: Option<Account>,
+        amount: u128,
+        fee: Option<u128>,
+        memo: Option<ByteBuf>,
+        created_at_time: Option<TimestampNanos>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct Approve {
+        from: Account,
+        spender: Account,
+        amount: u128,
+        expected_allowance: Option<u128>,
+        expires_at: Option<TimestampNanos>,
+        fee: Option<u128>,
+        memo: Option<ByteBuf>,
+        created_at_time: Option<TimestampNanos>,
+    }
+
+    pub async fn get_transactions(
+        ledger_canister_id: CanisterId,
+        start: u64,
+        length: u64,
+    ) -> CallResult<GetTransactionsResponse> {
+        let args = GetTransactionsRequest {
+            start: start.into(),
+            length: length.into(),
+        };
+        let (response,) = ic_cdk::call(ledger_canister_id, "get_transactions", (args,)).await?;
+        Ok(response)
+    }
+
+    pub async fn transactions_since(
+        ledger_canister_id: CanisterId,
+        start: u64,
+        length: u64,
+    ) -> CallResult<(Vec<IcrcTransaction>, u64)> {
+        let response = get_transactions(ledger_canister_id, start, length).await?;
+        let log_length = response.log_length as u64;
+
+        // Get the transactions from the archive canisters
+        let futures: Vec<_> = response
+            .archived_transactions
+            .into_iter()
+            .sorted_by_key(|a| a.start)
+            .map(get_transactions_from_archive)
+            .collect();
+
+        let archived_transactions = futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .collect::<CallResult<Vec<_>>>()?;
+
+        let transactions = join_contiguous(
+            start,
+            archived_transactions.into_iter().chain(iter::once((
+                response.first_index as u64,
+                response.transactions,
+            ))),
+        )?
+        .into_iter()
+        .map(|t| t.try_into().map_err(decode_error))
+        .collect::<CallResult<_>>()?;
+
+        Ok((transactions, log_length))
+    }
+
+    // Returns the index of the first transaction in the range along with the transactions
+    async fn get_transactions_from_archive(
+        range: ArchivedRange,
+    ) -> CallResult<(u64, Vec<Transaction>)> {
+        let end = range.start + range.length;
+
+        let mut transactions = Vec::new();
+        let mut start = range.start;
+
+        while start < end {
+            let args = GetTransactionsRequest {
+                start,
+                length: end - start,
+            };
+            let (response,): (TransactionRange,) =
+                ic_cdk::call(range.callback.principal, &range.callback.method, (args,)).await?;
+
+            if response.transactions.is_empty() {
+                return Err((
+                    RejectionCode::CanisterError,
+                    format!("Archive returned no transactions. Start: {start}"),
+                ));
+            }
+
+            start += response.transactions.len() as u128;
+            transactions.extend(response.transactions);
+        }
+
+        Ok((range.start as u64, transactions))
+    }
+
+    impl TryFrom<Transaction> for IcrcTransaction {
+        type Error = String;
+
+        fn try_from(t: Transaction) -> Result<Self, Self::Error> {
+            let (operation, memo, created_at_time) = if let Some(m) = t.mint {
+                let operation = IcrcOperation::Mint {
+                    to: m.to,
+                    amount: m.amount,
+                };
+                (operation, m.memo, m.created_at_time)
+            } else if let Some(b) = t.burn {
+                let operation = IcrcOperation::Burn {
+                    from: b.from,
+                    spender: b.spender,
+                    amount: b.amount,
+                };
+                (operation, b.memo, b.created_at_time)
+            } else if let Some(x) = t.transfer {
+                let operation = if let Some(spender) = x.spender {
+                    IcrcOperation::TransferFrom {
+                        from: x.from,
+                        to: x.to,
+                        spender,
+                        amount: x.amount,
+                        fee: x.fee,
+                    }
+                } else {
+                    IcrcOperation::Transfer {
+                        from: x.from,
+                        to: x.to,
+                        amount: x.amount,
+                        fee: x.fee,
+                    }
+                };
+                (operation, x.memo, x.created_at_time)
+            } else if let Some(a) = t.approve {
+                let operation = IcrcOperation::Approve {
+                    from: a.from,
+                    spender: a.spender,
+                    amount: a.amount,
+                    expected_allowance: a.expected_allowance,
+                    expires_at: a.expires_at,
+                    fee: a.fee,
+                };
+                (operation, a.memo, a.created_at_time)
+            } else {
+                return Err(format!("Unsupported transaction kind: {}", t.kind));
+            };
+
+            Ok(IcrcTransaction {
+                operation,
+                memo,
+                created_at_time,
+                timestamp: t.timestamp,
+            })
+        }
+    }
+}
+
+mod icrc3 {
+    use super::*;
+
+    #[derive(CandidType, Deserialize)]
+    struct GetBlocksArgs {
+        start: u128,
+        length: u128,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    pub struct GetBlocksResult {
+        pub log_length: u128,
+        blocks: Vec<BlockWithId>,
+        archived_blocks: Vec<ArchivedBlocks>,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct BlockWithId {
+        id: u128,
+        block: Value,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct ArchivedBlocks {
+        args: Vec<GetBlocksArgs>,
+        callback: Func,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    enum Value {
+        Blob(ByteBuf),
+        Text(String),
+        Nat(Nat),
+        Int(Int),
+        Array(Vec<Value>),
+        Map(Vec<(String, Value)>),
+    }
+
+    pub async fn get_blocks(
+        canister_id: CanisterId,
+        start: u64,
+        length: u64,
+    ) -> CallResult<GetBlocksResult> {
+        get_blocks_with_method(
+            canister_id,
+            "icrc3_get_blocks",
+            vec![GetBlocksArgs {
+                start: start.into(),
+                length: length.into(),
+            }],
+        )
+        .await
+    }
+
+    async fn get_blocks_with_method(
+        canister_id: CanisterId,
+        method_name: &str,
+        args: Vec<GetBlocksArgs>,
+    ) -> CallResult<GetBlocksResult> {
+        let (response,) = ic_cdk::call(canister_id, method_name, (args,)).await?;
+        Ok(response)
+    }
+
+    pub async fn blocks_since(
+        ledger_canister_id: CanisterId,
+        start: u64,
+        length: u64,
+    ) -> CallResult<(Vec<IcrcTransaction>, u64)> {
+        let response = get_blocks(ledger_canister_id, start, length).await?;
+        let log_length = response.log_length as u64;
+
+        // Get the blocks from the archive canisters
+        let futures: Vec<_> = response
+            .archived_blocks
+            .into_iter()
+            .flat_map(|a| {
+                let callback = a.callback;
+                a.args
+                    .into_iter()
+                    .map(move |range| get_blocks_from_archive(callback.clone(), range))
+            })
+            .collect();
+
+        let archived_blocks = futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .collect::<CallResult<Vec<_>>>()?;
+
+        let blocks = archived_blocks
+            .into_iter()
+            .flatten()
+            .chain(response.blocks)
+            .sorted_by_key(|b| b.id)
+            .map(|b| (b.id as u64, vec![b.block]));
+
+        let transactions = join_contiguous(start, blocks)?
+            .into_iter()
+            .map(|b| b.try_into().map_err(decode_error))
+            .collect::<CallResult<_>>()?;
+
+        Ok((transactions, log_length))
+    }
+
+    async fn get_blocks_from_archive(
+        callback: Func,
+        range: GetBlocksArgs,
+    ) -> CallResult<Vec<BlockWithId>> {
+        let end = range.start + range.length;
+
+        let mut blocks = Vec::new();
+        let mut start = range.start;
+
+        while start < end {
+            let args = vec![GetBlocksArgs {
+                start,
+                length: end - start,
+            }];
+            let response =
+                get_blocks_with_method(callback.principal, &callback.method, args).await?;
+
+            if response.blocks.is_empty() {
+                return Err((
+                    RejectionCode::CanisterError,
+                    format!("Archive returned no blocks. Start: {start}"),
+                ));
+            }
+
+            start += response.blocks.len() as u128;
+            blocks.extend(response.blocks);
+        }
+
+        Ok(blocks)
+    }
+
+    impl TryFrom<Value> for IcrcTransaction {
+        type Error = String;
+
+        fn try_from(block: Value) -> Result<Self, Self::Error> {
+            let block = block.into_map()?;
+            let tx = get(&block, "tx")?.as_map()?;
+
+            let op = match find(&block, "btype") {
+                Some(btype) => btype.as_text()?.trim_start_matches(char::is_numeric),
+                None => get(tx, "op")?.as_text()?,
+            };
+
+            let amount = get(tx, "amt")?.as_u128()?;
+            let fee = match find(tx, "fee").or_else(|| find(&block, "fee")) {
+                Some(v) => Some(v.as_u128()?),
+                None => None,
+            };
+            let spender = find(tx, "spender").map(|v| v.as_account()).transpose()?;
+
+            let operation = match op {
+                "mint" => IcrcOperation::Mint {
+                    to: get(tx, "to")?.as_account()?,
+                    amount,
+                },
+                "burn" => IcrcOperation::Burn {
+                    from: get(tx, "from")?.as_account()?,
+                    spender,
+                    amount,
+                },
+                "xfer" => {
+                    let from = get(tx, "from")?.as_account()?;
+                    let to = get(tx, "to")?.as_account()?;
+                    if let Some(spender) = spender {
+                        IcrcOperation::TransferFrom {
+                            from,
+                            to,
+                            spender,
+                            amount,
+                            fee,
+                        }
+                    } else {
+                        IcrcOperation::Transfer {
+                            from,
+                            to,
+                            amount,
+                            fee,
+                        }
+                    }
+                }
+                "approve" => IcrcOperation::Approve {
+                    from: get(tx, "from")?.as_account()?,
+                    spender: spender.ok_or("Approve is missing spender")?,
+                    amount,
+                    expected_allowance: find(tx, "expected_allowance")
+                        .map(|v| v.as_u128())
+                        .transpose()?,
+                    expires_at: find(tx, "expires_at").map(|v| v.as_u64()).transpose()?,
+                    fee,
+                },
+                _ => return Err(format!("Unsupported block type: {op}")),
+            };
+
+            Ok(IcrcTransaction {
+                operation,
+                memo: find(tx, "memo").map(|v| v.as_blob().cloned()).transpose()?,
+                created_at_time: find(tx, "ts").map(|v| v.as_u64()).transpose()?,
+                timestamp: get(&block, "ts")?.as_u64()?,
+            })
+        }
+    }
+
+    impl Value {
+        fn into_map(self) -> Result<Vec<(String, Value)>, String> {
+            if let Value::Map(m) = self {
+                Ok(m)
+            } else {
+                Err("Expected Map".to_string())
+            }
+        }
+
+        fn as_map(&self) -> Result<&[(String, Value)], String> {
+            if let Value::Map(m) = self {
+                Ok(m)
+            } else {
+                Err("Expected Map".to_string())
+            }
+        }
+
+        fn as_text(&self) -> Result<&str, String> {
+            if let Value::Text(t) = self {
+                Ok(t)
+            } else {
+                Err("Expected Text".to_string())
+            }
+        }
+
+        fn as_blob(&self) -> Result<&ByteBuf, String> {
+            if let Value::Blob(b) = self {
+                Ok(b)
+            } else {
+                Err("Expected Blob".to_string())
+            }
+        }
+
+        fn as_u128(&self) -> Result<u128, String> {
+            if let Value::Nat(n) = self {
+                n.0.clone()
+                    .try_into()
+                    .map_err(|_| "Nat out of range".to_string())
+            } else {
+                Err("Expected Nat".to_string())
+            }
+        }
+
+        fn as_u64(&self) -> Result<u64, String> {
+            self.as_u128()?
+                .try_into()
+                .map_err(|_| "Nat out of range".to_string())
+        }
+
+        // Accounts are encoded as an array containing the owner followed by an optional subaccount
+        fn as_account(&self) -> Result<Account, String> {
+            if let Value::Array(a) = self {
+                let owner = a
+                    .get(0)
+                    .ok_or("Account is missing owner")?
+                    .as_blob()
+                    .map(|b| Principal::from_slice(b))?;
+
+                let subaccount = match a.get(1) {
+                    Some(s) => Some(
+                        s.as_blob()?
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| "Invalid subaccount".to_string())?,
+                    ),
+                    None => None,
+                };
+
+                Ok(Account { owner, subaccount })
+            } else {
+                Err("Expected Array".to_string())
+            }
+        }
+    }
+
+    fn find<'a>(map: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+        map.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn get<'a>(map: &'a [(String, Value)], key: &str) -> Result<&'a Value, String> {
+        find(map, key).ok_or_else(|| format!("Missing field: {key}"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn transfer_block_is_parsed() {
+            let block = block(
+                Some("1xfer"),
+                vec![
+                    ("from", account(1, None)),
+                    ("to", account(2, Some([5; 32]))),
+                    ("amt", nat(100)),
+                    ("memo", Value::Blob(ByteBuf::from(vec![1, 2]))),
+                    ("ts", nat(5)),
+                ],
+                vec![("fee", nat(10))],
+            );
+
+            assert_eq!(
+                IcrcTransaction::try_from(block).unwrap(),
+                IcrcTransaction {
+                    operation: IcrcOperation::Transfer {
+                        from: principal_account(1, None),
+                        to: principal_account(2, Some([5; 32])),
+                        amount: 100,
+                        fee: Some(10),
+                    },
+                    memo: Some(ByteBuf::from(vec![1, 2])),
+                    created_at_time: Some(5),
+                    timestamp: 1000,
+                }
+            );
+        }
+
+        #[test]
+        fn operation_is_read_from_tx_if_block_has_no_btype() {
+            let block = block(
+                None,
+                vec![
+                    ("op", text("xfer")),
+                    ("from", account(1, None)),
+                    ("to", account(2, None)),
+                    ("spender", account(3, None)),
+                    ("amt", nat(100)),
+                ],
+                vec![],
+            );
+
+            assert_eq!(
+                IcrcTransaction::try_from(block).unwrap().operation,
+                IcrcOperation::TransferFrom {
+                    from: principal_account(1, None),
+                    to: principal_account(2, None),
+                    spender: principal_account(3, None),
+                    amount: 100,
+                    fee: None,
+                }
+            );
+        }
+
+        #[test]
+        fn approve_without_spender_is_an_error() {
+            let block = block(
+                Some("2approve"),
+                vec![("from", account(1, None)), ("amt", nat(100))],
+                vec![],
+            );
+
+            assert!(IcrcTransaction::try_from(block).is_err());
+        }
+
+        #[test]
+        fn invalid_values_are_errors() {
+            assert!(IcrcTransaction::try_from(block(Some("1xfer"), vec![], vec![])).is_err());
+            assert!(IcrcTransaction::try_from(block(
+                Some("9unknown"),
+                vec![("amt", nat(1))],
+                vec![]
+            ))
+            .is_err());
+            assert!(text("x").into_map().is_err());
+            assert!(text("x").as_u128().is_err());
+            assert!(Value::Nat(Nat::from(u128::MAX)).as_u64().is_err());
+            assert!(Value::Array(vec![]).as_account().is_err());
+            assert!(Value::Array(vec![
+                Value::Blob(ByteBuf::from(vec![1])),
+                Value::Blob(ByteBuf::from(vec![0; 31]))
+            ])
+            .as_account()
+            .is_err());
+        }
+
+        fn block(btype: Option<&str>, tx: Vec<(&str, Value)>, extra: Vec<(&str, Value)>) -> Value {
+            let mut block = vec![("tx", map(tx)), ("ts", nat(1000))];
+            if let Some(btype) = btype {
+                block.push(("btype", text(btype)));
+            }
+            block.extend(extra);
+            map(block)
+        }
+
+        fn map(entries: Vec<(&str, Value)>) -> Value {
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+        }
+
+        fn account(owner: u8, subaccount: Option<[u8; 32]>) -> Value {
+            let mut values = vec![Value::Blob(ByteBuf::from(vec![owner]))];
+            if let Some(subaccount) = subaccount {
+                values.push(Value::Blob(ByteBuf::from(subaccount.to_vec())));
+            }
+            Value::Array(values)
+        }
+
+        fn principal_account(owner: u8, subaccount: Option<[u8; 32]>) -> Account {
+            Account {
+                owner: Principal::from_slice(&[owner]),
+                subaccount,
+            }
+        }
+
+        fn nat(value: u64) -> Value {
+            Value::Nat(Nat::from(value))
+        }
+
+        fn text(value: &str) -> Value {
+            Value::Text(value.to_string())
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::read_state;
+use crate::timers::{schedule, Job};
+
+pub mod push_notifications;
+pub mod sync_ledger_transactions;
+
+// Timers don't survive upgrades, so this schedules a sync of each enabled token plus a push of any
+// queued notifications whenever the canister is initialized
+pub fn start() {
+    read_state(|state| {
+        let now = state.env.now();
+
+        for token in state.data.tokens.iter() {
+            if token.ledger_sync_state().enabled() {
+                schedule(Job::SyncToken(token.ledger_canister_id()), now);
+            }
+        }
+
+        schedule(Job::PushNotifications, now);
+    });
+}
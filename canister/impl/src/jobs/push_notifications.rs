@@ -0,0 +1,103 @@
+use crate::model::notifications::{MarkFailedResult, Notification};
+use crate::timers::{schedule, Job};
+use crate::{mutate_state, State};
+use ic_cdk::api::call::CallResult;
+use tracing::{error, warn};
+use transaction_notifier::NotificationArgs;
+
+const MAX_NOTIFICATIONS_PER_BATCH: usize = 5;
+
+pub fn run() {
+    if let Some(batch) = mutate_state(next_batch) {
+        ic_cdk::spawn(push_batch(batch));
+    }
+}
+
+struct Batch {
+    notifications: Vec<Notification>,
+    method_name: String,
+    icrc_method_name: String,
+}
+
+fn next_batch(state: &mut State) -> Option<Batch> {
+    let now = state.env.now();
+    state.data.notifications.requeue_due_retries(now);
+
+    // Each push schedules another run once it completes, so this only needs to cover retries
+    if let Some(retry_at) = state.data.notifications.next_retry_at() {
+        schedule(Job::PushNotifications, retry_at);
+    }
+
+    if state.data.notifications.is_queue_empty() {
+        return None;
+    }
+
+    let notifications = state
+        .data
+        .notifications
+        .next_batch(MAX_NOTIFICATIONS_PER_BATCH);
+
+    if !notifications.is_empty() {
+        Some(Batch {
+            notifications,
+            method_name: state.data.notification_method_name.clone(),
+            icrc_method_name: state.data.icrc_notification_method_name.clone(),
+        })
+    } else {
+        None
+    }
+}
+
+async fn push_batch(batch: Batch) {
+    let futures: Vec<_> = batch
+        .notifications
+        .into_iter()
+        .map(|n| push(n, &batch.method_name, &batch.icrc_method_name))
+        .collect();
+
+    futures::future::join_all(futures).await;
+}
+
+async fn push(notification: Notification, method_name: &str, icrc_method_name: &str) {
+    let canister_id = notification.canister_id;
+    let response: CallResult<()> = match &notification.args {
+        NotificationArgs::Icp(args) => ic_cdk::call(canister_id, method_name, (args,)).await,
+        NotificationArgs::Icrc(args) => ic_cdk::call(canister_id, icrc_method_name, (args,)).await,
+    };
+
+    match response {
+        Ok(_) => mutate_state(|state| {
+            let now = state.env.now();
+            state.data.notifications.mark_sent(&notification, now);
+
+            schedule(Job::PushNotifications, now);
+        }),
+        Err(error) => mutate_state(|state| {
+            let block_index = notification.args.block_index();
+            let now = state.env.now();
+
+            schedule(Job::PushNotifications, now);
+
+            match state
+                .data
+                .notifications
+                .mark_failed(notification, &error, now)
+            {
+                MarkFailedResult::RetryScheduled(retry_at) => warn!(
+                    %canister_id,
+                    block_index,
+                    retry_at,
+                    ?error,
+                    "Failed to push notification, will retry"
+                ),
+                MarkFailedResult::DeadLettered(dead_letter_id) => error!(
+                    %canister_id,
+                    block_index,
+                    dead_letter_id,
+                    ?error,
+                    "Failed to push notification, moved to dead letters"
+                ),
+            }
+        }),
+    }
+}
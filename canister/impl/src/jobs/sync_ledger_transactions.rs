@@ -0,0 +1,531 @@
+use crate::icp_block_hash::{possible_hashes, BlockHash};
+use crate::icp_ledger::IcpBlock;
+use crate::model::ledger_sync_state::{SyncResult, TryStartSyncResult, Version};
+use crate::model::subscriptions::TransactionSummary;
+use crate::timers::{schedule, Job};
+use crate::{icp_ledger, icrc_ledger, mutate_state, State};
+use ic_cdk::api::call::CallResult;
+use ic_ledger_types::{AccountIdentifier, BlockIndex, Operation, Tokens};
+use tracing::error;
+use transaction_notifier::Direction::{Incoming, Outgoing};
+use transaction_notifier::{
+    Account, Direction, IcrcOperation, IcrcTransaction, LedgerStandard, NotificationArgs,
+    NotifyIcrcTransactionArgs, NotifyTransactionArgs, OperationKind,
+};
+use types::{CanisterId, TimestampMillis};
+
+const MAX_BLOCKS_PER_SYNC: u64 = 1000;
+const NANOS_PER_MILLISECOND: u64 = 1_000_000;
+
+struct TokenToSync {
+    token_symbol: String,
+    ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
+    from_block: BlockIndex,
+    last_block_hash: Option<BlockHash>,
+    version: Version,
+}
+
+enum Transactions {
+    Icp(Vec<IcpBlock>),
+    Icrc(Vec<IcrcTransaction>),
+}
+
+impl Transactions {
+    fn len(&self) -> usize {
+        match self {
+            Transactions::Icp(blocks) => blocks.len(),
+            Transactions::Icrc(transactions) => transactions.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn last_timestamp(&self) -> Option<TimestampMillis> {
+        let timestamp_nanos = match self {
+            Transactions::Icp(blocks) => blocks.last()?.block.timestamp.timestamp_nanos,
+            Transactions::Icrc(transactions) => transactions.last()?.timestamp,
+        };
+        Some(timestamp_nanos / NANOS_PER_MILLISECOND)
+    }
+}
+
+struct SyncProgress {
+    next_block_to_sync: BlockIndex,
+    last_block_hash: Option<BlockHash>,
+    last_block_timestamp: Option<TimestampMillis>,
+}
+
+pub fn run(ledger_canister_id: CanisterId) {
+    if let Some(token_to_sync) = mutate_state(|state| try_start(ledger_canister_id, state)) {
+        ic_cdk::spawn(sync_token(token_to_sync));
+    }
+}
+
+fn try_start(ledger_canister_id: CanisterId, state: &mut State) -> Option<TokenToSync> {
+    let now = state.env.now();
+
+    state.data.tokens.update(ledger_canister_id, |t| {
+        if let TryStartSyncResult::Success(from_block, version) =
+            t.ledger_sync_state_mut().try_start(now)
+        {
+            Some(TokenToSync {
+                token_symbol: t.token_symbol().to_string(),
+                ledger_canister_id: t.ledger_canister_id(),
+                ledger_standard: t.ledger_standard(),
+                from_block,
+                last_block_hash: t.ledger_sync_state().last_block_hash(),
+                version,
+            })
+        } else {
+            None
+        }
+    })?
+}
+
+async fn sync_token(token_to_sync: TokenToSync) {
+    let mut progress = None;
+    let mut chain_length = None;
+    let mut result = SyncResult::Failed;
+
+    match transactions_since(&token_to_sync, MAX_BLOCKS_PER_SYNC).await {
+        Ok((transactions, length)) => match verify_hash_chain(&token_to_sync, &transactions) {
+            Ok(last_block_hash) => {
+                result = match transactions.len() as u64 {
+                    0 => SyncResult::NoNewBlocks,
+                    MAX_BLOCKS_PER_SYNC => SyncResult::FullBatch,
+                    _ => SyncResult::NewBlocks,
+                };
+                chain_length = Some(length);
+                if !transactions.is_empty() {
+                    mutate_state(|state| {
+                        // The token may have been removed, or its sync position reset, while the
+                        // blocks were being fetched
+                        if !state.data.tokens.is_current_sync(
+                            token_to_sync.ledger_canister_id,
+                            token_to_sync.version,
+                        ) {
+                            return;
+                        }
+
+                        progress = Some(SyncProgress {
+                            next_block_to_sync: token_to_sync.from_block
+                                + (transactions.len() as u64),
+                            last_block_hash,
+                            last_block_timestamp: transactions.last_timestamp(),
+                        });
+
+                        enqueue_notifications(
+                            &token_to_sync.token_symbol,
+                            token_to_sync.ledger_canister_id,
+                            transactions,
+                            token_to_sync.from_block,
+                            state,
+                        );
+
+                        schedule(Job::PushNotifications, state.env.now());
+                    });
+                }
+            }
+            Err(block_index) => {
+                error!(
+                    token_symbol = token_to_sync.token_symbol.as_str(),
+                    block_index, "Block hash chain mismatch, halting sync"
+                );
+                mutate_state(|state| {
+                    state
+                        .data
+                        .tokens
+                        .update(token_to_sync.ledger_canister_id, |t| {
+                            t.ledger_sync_state_mut()
+                                .mark_hash_chain_mismatch(block_index, token_to_sync.version)
+                        })
+                });
+            }
+        },
+        Err(error) => error!(
+            token_symbol = token_to_sync.token_symbol.as_str(),
+            ledger_canister_id = %token_to_sync.ledger_canister_id,
+            ?error,
+            "Failed to get blocks from ledger"
+        ),
+    }
+
+    mutate_state(|state| {
+        mark_sync_complete(
+            token_to_sync.ledger_canister_id,
+            progress,
+            chain_length,
+            result,
+            token_to_sync.version,
+            state,
+        )
+    });
+}
+
+// Checks that the parent hash of each block matches the hash of the block before it, starting
+// from the last block processed by the previous sync. Returns the hash of the final block, or
+// the index of the first block which doesn't link up. ICRC ledgers are not checked.
+//
+// Where a block may have more than one hash (see `possible_hashes`) the next block's parent hash
+// may match any of them. If the final block's hash is ambiguous then None is returned, so the
+// first block of the next sync goes unchecked.
+fn verify_hash_chain(
+    token_to_sync: &TokenToSync,
+    transactions: &Transactions,
+) -> Result<Option<BlockHash>, BlockIndex> {
+    let blocks = match transactions {
+        Transactions::Icp(blocks) => blocks,
+        Transactions::Icrc(_) => return Ok(None),
+    };
+
+    verify_icp_blocks(
+        token_to_sync.from_block,
+        token_to_sync.last_block_hash,
+        blocks,
+    )
+}
+
+fn verify_icp_blocks(
+    from_block: BlockIndex,
+    last_block_hash: Option<BlockHash>,
+    blocks: &[IcpBlock],
+) -> Result<Option<BlockHash>, BlockIndex> {
+    let mut previous_hashes: Vec<BlockHash> = last_block_hash.into_iter().collect();
+
+    for (index, block) in blocks.iter().enumerate() {
+        if !previous_hashes.is_empty()
+            && !block
+                .block
+                .parent_hash
+                .map_or(false, |h| previous_hashes.contains(&h))
+        {
+            return Err(from_block + (index as u64));
+        }
+        previous_hashes = possible_hashes(block);
+    }
+
+    match previous_hashes.as_slice() {
+        [hash] => Ok(Some(*hash)),
+        _ => Ok(None),
+    }
+}
+
+// Returns the transactions along with the ledger's current chain length
+async fn transactions_since(
+    token_to_sync: &TokenToSync,
+    length: u64,
+) -> CallResult<(Transactions, u64)> {
+    if token_to_sync.ledger_standard == LedgerStandard::Icp {
+        icp_ledger::blocks_since(
+            token_to_sync.ledger_canister_id,
+            token_to_sync.from_block,
+            length,
+        )
+        .await
+        .map(|(blocks, chain_length)| (Transactions::Icp(blocks), chain_length))
+    } else {
+        icrc_ledger::transactions_since(
+            token_to_sync.ledger_canister_id,
+            token_to_sync.ledger_standard,
+            token_to_sync.from_block,
+            length,
+        )
+        .await
+        .map(|(transactions, chain_length)| (Transactions::Icrc(transactions), chain_length))
+    }
+}
+
+// Schedules the next sync unless syncing has been disabled or halted in the meantime, in which
+// case it is scheduled again when the token's config is updated
+fn mark_sync_complete(
+    ledger_canister_id: CanisterId,
+    progress: Option<SyncProgress>,
+    chain_length: Option<u64>,
+    result: SyncResult,
+    version: Version,
+    state: &mut State,
+) {
+    let now = state.env.now();
+
+    let next_sync_delay = state.data.tokens.update(ledger_canister_id, |token_data| {
+        let ledger_sync_state = token_data.ledger_sync_state_mut();
+
+        if let Some(progress) = progress {
+            ledger_sync_state.set_next_block_to_sync(
+                progress.next_block_to_sync,
+                progress.last_block_hash,
+                progress.last_block_timestamp,
+                version,
+            );
+        }
+        if let Some(chain_length) = chain_length {
+            ledger_sync_state.set_chain_length(chain_length);
+        }
+
+        let delay = ledger_sync_state.mark_sync_complete(result, now);
+
+        (ledger_sync_state.enabled() && ledger_sync_state.hash_chain_mismatch().is_none())
+            .then_some(delay)
+    });
+
+    if let Some(delay) = next_sync_delay.flatten() {
+        schedule(Job::SyncToken(ledger_canister_id), now + delay);
+    }
+}
+
+fn enqueue_notifications(
+    token_symbol: &str,
+    ledger_canister_id: CanisterId,
+    transactions: Transactions,
+    from_block_index: BlockIndex,
+    state: &mut State,
+) {
+    match transactions {
+        Transactions::Icp(blocks) => enqueue_icp_notifications(
+            token_symbol,
+            ledger_canister_id,
+            blocks,
+            from_block_index,
+            state,
+        ),
+        Transactions::Icrc(transactions) => enqueue_icrc_notifications(
+            token_symbol,
+            ledger_canister_id,
+            transactions,
+            from_block_index,
+            state,
+        ),
+    }
+}
+
+fn enqueue_icp_notifications(
+    token_symbol: &str,
+    ledger_canister_id: CanisterId,
+    blocks: Vec<IcpBlock>,
+    from_block_index: BlockIndex,
+    state: &mut State,
+) {
+    let now = state.env.now();
+    let subscriptions = &state.data.subscriptions;
+
+    for (block_index, block) in blocks
+        .into_iter()
+        .enumerate()
+        .map(|(index, block)| ((index as u64) + from_block_index, block))
+    {
+        let operation = if let Some(op) = &block.block.transaction.operation {
+            op
+        } else {
+            continue;
+        };
+        let (account_identifiers, kind, amount) = extract_operation_details(operation);
+        let summary = TransactionSummary {
+            token_symbol,
+            kind,
+            amount: amount.e8s().into(),
+        };
+        let canisters_to_notify = subscriptions.canisters_to_notify(&account_identifiers, &summary);
+
+        for canister_id in canisters_to_notify {
+            state.data.notifications.enqueue(
+                Notification {
+                    canister_id,
+                    args: NotificationArgs::Icp(NotifyTransactionArgs {
+                        token_symbol: token_symbol.to_string(),
+                        ledger_canister_id,
+                        block_index,
+                        block: block.block.clone(),
+                        notification_id: 0, // Assigned when the notification is enqueued
+                    }),
+                    attempts: 0,
+                },
+                now,
+            )
+        }
+    }
+}
+
+fn enqueue_icrc_notifications(
+    token_symbol: &str,
+    ledger_canister_id: CanisterId,
+    transactions: Vec<IcrcTransaction>,
+    from_block_index: BlockIndex,
+    state: &mut State,
+) {
+    let now = state.env.now();
+    let subscriptions = &state.data.subscriptions;
+
+    for (block_index, transaction) in transactions
+        .into_iter()
+        .enumerate()
+        .map(|(index, transaction)| ((index as u64) + from_block_index, transaction))
+    {
+        let (accounts, kind, amount) = extract_icrc_operation_details(&transaction.operation);
+        let summary = TransactionSummary {
+            token_symbol,
+            kind,
+            amount,
+        };
+        let canisters_to_notify = subscriptions.canisters_to_notify_icrc(&accounts, &summary);
+
+        for canister_id in canisters_to_notify {
+            state.data.notifications.enqueue(
+                Notification {
+                    canister_id,
+                    args: NotificationArgs::Icrc(NotifyIcrcTransactionArgs {
+                        token_symbol: token_symbol.to_string(),
+                        ledger_canister_id,
+                        block_index,
+                        transaction: transaction.clone(),
+                        notification_id: 0, // Assigned when the notification is enqueued
+                    }),
+                    attempts: 0,
+                },
+                now,
+            )
+        }
+    }
+}
+
+fn extract_operation_details(
+    operation: &Operation,
+) -> (
+    Vec<(AccountIdentifier, Option<Direction>)>,
+    OperationKind,
+    Tokens,
+) {
+    match operation {
+        Operation::Transfer {
+            from, to, amount, ..
+        } => (
+            vec![(*from, Some(Outgoing)), (*to, Some(Incoming))],
+            OperationKind::Transfer,
+            *amount,
+        ),
+        Operation::Mint { to, amount } => {
+            (vec![(*to, Some(Incoming))], OperationKind::Mint, *amount)
+        }
+        Operation::Burn { from, amount } => {
+            (vec![(*from, Some(Outgoing))], OperationKind::Burn, *amount)
+        }
+    }
+}
+
+// Spenders are included without a direction since the funds neither leave nor arrive in their
+// accounts
+fn extract_icrc_operation_details(
+    operation: &IcrcOperation,
+) -> (Vec<(Account, Option<Direction>)>, OperationKind, u128) {
+    match operation {
+        IcrcOperation::Mint { to, amount } => {
+            (vec![(*to, Some(Incoming))], OperationKind::Mint, *amount)
+        }
+        IcrcOperation::Burn {
+            from,
+            spender,
+            amount,
+        } => {
+            let mut accounts = vec![(*from, Some(Outgoing))];
+            accounts.extend(spender.map(|s| (s, None)));
+            (accounts, OperationKind::Burn, *amount)
+        }
+        IcrcOperation::Transfer {
+            from, to, amount, ..
+        } => (
+            vec![(*from, Some(Outgoing)), (*to, Some(Incoming))],
+            OperationKind::Transfer,
+            *amount,
+        ),
+        IcrcOperation::TransferFrom {
+            from,
+            to,
+            spender,
+            amount,
+            ..
+        } => (
+            vec![
+                (*from, Some(Outgoing)),
+                (*to, Some(Incoming)),
+                (*spender, None),
+            ],
+            OperationKind::Transfer,
+            *amount,
+        ),
+        IcrcOperation::Approve {
+            from,
+            spender,
+            amount,
+            ..
+        } => (
+            vec![(*from, Some(Outgoing)), (*spender, None)],
+            OperationKind::Approve,
+            *amount,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_ledger_types::{Block, Memo, Timestamp, Transaction};
+
+    #[test]
+    fn blocks_linking_to_either_possible_hash_are_accepted() {
+        // The first block's created_at_time matches its timestamp so it has two possible hashes
+        let first = block(None, 1, 1);
+        let hashes = possible_hashes(&first);
+        assert_eq!(hashes.len(), 2);
+
+        for hash in hashes {
+            let blocks = vec![first.clone(), block(Some(hash), 2, 3)];
+            let expected = possible_hashes(&blocks[1])[0];
+
+            assert_eq!(verify_icp_blocks(10, None, &blocks), Ok(Some(expected)));
+        }
+    }
+
+    #[test]
+    fn block_not_linking_to_previous_block_is_rejected() {
+        let first = block(None, 1, 2);
+        let blocks = vec![first, block(Some([0; 32]), 2, 3)];
+
+        assert_eq!(verify_icp_blocks(10, None, &blocks), Err(11));
+    }
+
+    #[test]
+    fn first_block_is_checked_against_last_block_hash() {
+        let blocks = vec![block(Some([1; 32]), 1, 2)];
+
+        assert!(verify_icp_blocks(10, Some([1; 32]), &blocks).is_ok());
+        assert_eq!(verify_icp_blocks(10, Some([2; 32]), &blocks), Err(10));
+    }
+
+    #[test]
+    fn ambiguous_last_block_hash_is_not_returned() {
+        let blocks = vec![block(None, 1, 1)];
+
+        assert_eq!(verify_icp_blocks(10, None, &blocks), Ok(None));
+    }
+
+    fn block(parent_hash: Option<BlockHash>, timestamp: u64, created_at_time: u64) -> IcpBlock {
+        IcpBlock {
+            block: Block {
+                parent_hash,
+                transaction: Transaction {
+                    memo: Memo(0),
+                    operation: None,
+                    created_at_time: Timestamp {
+                        timestamp_nanos: created_at_time,
+                    },
+                },
+                timestamp: Timestamp {
+                    timestamp_nanos: timestamp,
+                },
+            },
+            icrc1_memo: None,
+        }
+    }
+}
@@ -1,8 +1,17 @@
 use crate::env::Environment;
+use crate::memory::{
+    get_audit_log_memory, get_icrc_account_identifiers_memory, get_notification_queue_memory,
+    get_notification_queues_memory, get_subscriptions_by_canister_memory, get_subscriptions_memory,
+    get_tokens_memory, get_upgrades_memory, heap_memory_used, memory_used, stable_memory_used,
+};
+use crate::model::audit_log::{AuditLog, AuditLogEntry};
 use crate::model::ledger_sync_state::LedgerSyncState;
-use crate::model::notifications::Notifications;
-use crate::model::subscriptions::Subscriptions;
+use crate::model::notifications::{Notifications, NotificationsPreviousVersion};
+use crate::model::roles::Roles;
+use crate::model::subscriber_allowlist::SubscriberAllowlist;
+use crate::model::subscriptions::{Subscriptions, SubscriptionsPreviousVersion};
 use crate::model::token_data::TokenData;
+use crate::model::tokens::Tokens;
 use candid::{CandidType, Principal};
 use canister_logger::LogMessagesWrapper;
 use canister_state_macros::canister_state;
@@ -10,13 +19,25 @@ use ic_ledger_types::BlockIndex;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use types::{CanisterId, Cycles, TimestampMillis, Timestamped, Version};
+use std::fmt::Debug;
+use transaction_notifier::{HealthThresholds, LedgerStandard, Role};
+use types::{CanisterId, Cycles, Milliseconds, TimestampMillis, Timestamped, Version};
 
+mod authorization;
+mod block_ranges;
 mod env;
 mod guards;
+mod health;
+mod icp_block_hash;
+mod icp_ledger;
+mod icrc_ledger;
+mod jobs;
 mod lifecycle;
+mod memory;
 mod model;
+mod prometheus;
 mod queries;
+mod timers;
 mod updates;
 
 thread_local! {
@@ -36,68 +57,309 @@ impl State {
         State { env, data }
     }
 
+    // Runs a privileged call and records it in the audit log
+    pub fn audited<A: Debug, R: Debug, F: FnOnce(A, &mut State) -> R>(
+        &mut self,
+        endpoint: &str,
+        args: A,
+        f: F,
+    ) -> R {
+        let args_text = format!("{args:?}");
+        let response = f(args, self);
+        self.record_audit(endpoint, args_text, &response);
+        response
+    }
+
+    // Calls made by a canister to manage only its own subscriptions are not audited, whereas those
+    // made on behalf of other canisters are
+    pub fn audited_unless_caller_is_subscriber<
+        A: Debug,
+        R: Debug,
+        F: FnOnce(A, &mut State) -> R,
+    >(
+        &mut self,
+        endpoint: &str,
+        canister_ids: &[CanisterId],
+        args: A,
+        f: F,
+    ) -> R {
+        let caller = self.env.caller();
+        if canister_ids.iter().all(|c| *c == caller) {
+            f(args, self)
+        } else {
+            self.audited(endpoint, args, f)
+        }
+    }
+
+    pub fn record_audit<R: Debug>(&mut self, endpoint: &str, args: String, response: &R) {
+        let entry = AuditLogEntry {
+            timestamp: self.env.now(),
+            caller: self.env.caller(),
+            endpoint: endpoint.to_string(),
+            args,
+            response: format!("{response:?}"),
+        };
+        self.data.audit_log.push(entry);
+    }
+
     pub fn metrics(&self) -> Metrics {
+        let now = self.env.now();
+        let tokens: Vec<_> = self.data.tokens.iter().map(|t| t.metrics(now)).collect();
+        let hash_chain_mismatches = tokens
+            .iter()
+            .filter(|t| t.hash_chain_mismatch.is_some())
+            .count() as u64;
+
+        let memory = MemoryMetrics {
+            heap: heap_memory_used(),
+            stable: stable_memory_used(),
+            upgrades: memory_used(&get_upgrades_memory()),
+            tokens: memory_used(&get_tokens_memory()),
+            subscriptions: memory_used(&get_subscriptions_memory())
+                + memory_used(&get_subscriptions_by_canister_memory())
+                + memory_used(&get_icrc_account_identifiers_memory()),
+            notification_queue: memory_used(&get_notification_queue_memory())
+                + memory_used(&get_notification_queues_memory()),
+            audit_log: memory_used(&get_audit_log_memory()),
+            log_messages: LOG_MESSAGES.with(|l| log_messages_size(&l.borrow())),
+        };
+
         Metrics {
-            now: self.env.now(),
-            memory_used: 0,
+            now,
+            memory_used: memory.heap + memory.stable,
+            memory,
             cycles_balance: self.env.cycles_balance(),
             wasm_version: WASM_VERSION.with(|v| **v.borrow()),
-            tokens: self.data.tokens.values().map(|t| t.metrics()).collect(),
-            subscriptions: self.data.subscriptions.len() as u64,
+            tokens,
+            hash_chain_mismatches,
+            subscriptions: self.data.subscriptions.len(),
             notifications_sent: self.data.notifications.total_sent(),
-            notifications_queued: self.data.notifications.queue_len().try_into().unwrap(),
+            notifications_failed: self.data.notifications.total_failed(),
+            notifications_queued: self.data.notifications.queue_len(),
+            notifications_pending_retry: self
+                .data
+                .notifications
+                .pending_retry_count()
+                .try_into()
+                .unwrap(),
+            max_notification_attempts: self.data.notifications.max_attempts(),
+            dead_letters: self
+                .data
+                .notifications
+                .dead_letter_count()
+                .try_into()
+                .unwrap(),
+            pull_subscribers: self
+                .data
+                .notifications
+                .pull_subscriber_count()
+                .try_into()
+                .unwrap(),
+            notifications_pending_pull: self
+                .data
+                .notifications
+                .pending_pull_count()
+                .try_into()
+                .unwrap(),
+            notifications_retried: self.data.notifications.total_retried(),
+            failing_subscribers: self
+                .data
+                .notifications
+                .failing_subscriber_count()
+                .try_into()
+                .unwrap(),
             test_mode: self.data.test_mode,
         }
     }
 }
 
+// Tokens, subscriptions and the audit log are held in stable memory so are not serialized during
+// upgrades. Fields are encoded by position, so new fields must be appended at the end and marked
+// `#[serde(default)]` to remain readable from the previous version.
 #[derive(Serialize, Deserialize)]
 struct Data {
     admins: HashSet<Principal>,
     notification_method_name: String,
-    tokens: HashMap<String, TokenData>,
+    #[serde(skip)]
+    tokens: Tokens,
+    #[serde(skip)]
     subscriptions: Subscriptions,
     notifications: Notifications,
     test_mode: bool,
+    #[serde(default)]
+    subscriber_allowlist: SubscriberAllowlist,
+    #[serde(default = "default_icrc_notification_method_name")]
+    icrc_notification_method_name: String,
+    #[serde(default)]
+    roles: Roles,
+    #[serde(skip)]
+    audit_log: AuditLog,
+    #[serde(default)]
+    health_thresholds: HealthThresholds,
 }
 
 impl Data {
     pub fn new(
         admins: HashSet<Principal>,
         notification_method_name: String,
+        icrc_notification_method_name: String,
+        max_notification_attempts: u32,
         test_mode: bool,
     ) -> Data {
         Data {
             admins,
             notification_method_name,
-            tokens: HashMap::default(),
+            tokens: Tokens::default(),
             subscriptions: Subscriptions::default(),
-            notifications: Notifications::default(),
+            notifications: Notifications::new(max_notification_attempts),
             test_mode,
+            subscriber_allowlist: SubscriberAllowlist::default(),
+            icrc_notification_method_name,
+            roles: Roles::default(),
+            audit_log: AuditLog::default(),
+            health_thresholds: HealthThresholds::default(),
         }
     }
+
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        self.admins.contains(principal) || self.roles.has(principal, role)
+    }
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+// The layout in which `Data` was serialized into stable memory prior to the introduction of the
+// stable memory backed structures
+#[derive(Deserialize)]
+struct DataPreviousVersion {
+    admins: HashSet<Principal>,
+    notification_method_name: String,
+    tokens: HashMap<String, TokenData>,
+    subscriptions: SubscriptionsPreviousVersion,
+    notifications: NotificationsPreviousVersion,
+    test_mode: bool,
+    #[serde(default)]
+    subscriber_allowlist: SubscriberAllowlist,
+    #[serde(default = "default_icrc_notification_method_name")]
+    icrc_notification_method_name: String,
+}
+
+impl From<DataPreviousVersion> for Data {
+    fn from(mut previous: DataPreviousVersion) -> Self {
+        for token in previous.tokens.values_mut() {
+            token.ledger_sync_state_mut().migrate_timestamps_to_millis();
+        }
+
+        Data {
+            admins: previous.admins,
+            notification_method_name: previous.notification_method_name,
+            tokens: previous.tokens.into(),
+            subscriptions: previous.subscriptions.into(),
+            notifications: previous.notifications.into(),
+            test_mode: previous.test_mode,
+            subscriber_allowlist: previous.subscriber_allowlist,
+            icrc_notification_method_name: previous.icrc_notification_method_name,
+            roles: Roles::default(),
+            audit_log: AuditLog::default(),
+            health_thresholds: HealthThresholds::default(),
+        }
+    }
+}
+
+fn log_messages_size(messages: &LogMessagesWrapper) -> u64 {
+    messages
+        .logs
+        .get(0)
+        .into_iter()
+        .chain(messages.traces.get(0))
+        .map(|m| m.json.len() as u64)
+        .sum()
+}
+
+fn default_icrc_notification_method_name() -> String {
+    "notify_icrc_transaction".to_string()
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Metrics {
     pub now: TimestampMillis,
     pub memory_used: u64,
+    pub memory: MemoryMetrics,
     pub cycles_balance: Cycles,
     pub wasm_version: Version,
     pub tokens: Vec<TokenMetrics>,
+    // The number of tokens whose sync has been halted because their block hash chain didn't link up
+    pub hash_chain_mismatches: u64,
     pub subscriptions: u64,
     pub notifications_sent: u64,
+    pub notifications_failed: u64,
     pub notifications_queued: u64,
+    pub notifications_pending_retry: u64,
+    pub max_notification_attempts: u32,
+    pub dead_letters: u64,
+    pub pull_subscribers: u64,
+    pub notifications_pending_pull: u64,
+    pub notifications_retried: u64,
+    // The number of subscribers whose latest push attempt failed. Per subscriber stats are only
+    // available to observers, via `subscriber_stats`.
+    pub failing_subscribers: u64,
     pub test_mode: bool,
 }
 
+// All values are in bytes
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MemoryMetrics {
+    pub heap: u64,
+    pub stable: u64,
+    // Holds the serialized `Data` as of the last upgrade
+    pub upgrades: u64,
+    pub tokens: u64,
+    pub subscriptions: u64,
+    pub notification_queue: u64,
+    pub audit_log: u64,
+    // An estimate of the heap memory held by the log and trace buffers
+    pub log_messages: u64,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct TokenMetrics {
     pub token_symbol: String,
     pub ledger_canister_id: CanisterId,
+    pub ledger_standard: LedgerStandard,
     pub sync_enabled: bool,
     pub synced_up_to: Option<BlockIndex>,
     pub last_sync_started_at: TimestampMillis,
     pub last_successful_sync: TimestampMillis,
     pub last_failed_sync: TimestampMillis,
+    pub hash_chain_mismatch: Option<BlockIndex>,
+    pub sync_interval: Milliseconds,
+    pub sync_delay: Milliseconds,
+    // The ledger's chain length as of the last successful sync
+    pub chain_length: Option<u64>,
+    pub last_block_timestamp: Option<TimestampMillis>,
+    pub blocks_behind: Option<u64>,
+    // The age of the last block synced, or 0 if there are no more blocks to sync
+    pub seconds_behind: Option<u64>,
+}
+
+#[cfg(test)]
+impl TokenMetrics {
+    // An enabled token which has yet to sync, for tests to override as needed
+    pub fn test_default(token_symbol: &str) -> TokenMetrics {
+        TokenMetrics {
+            token_symbol: token_symbol.to_string(),
+            ledger_canister_id: Principal::from_slice(&[1]),
+            ledger_standard: LedgerStandard::Icp,
+            sync_enabled: true,
+            synced_up_to: None,
+            last_sync_started_at: 0,
+            last_successful_sync: 0,
+            last_failed_sync: 0,
+            hash_chain_mismatch: None,
+            sync_interval: 1000,
+            sync_delay: 0,
+            chain_length: None,
+            last_block_timestamp: None,
+            blocks_behind: None,
+            seconds_behind: None,
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::env::CanisterEnv;
 use crate::lifecycle::{init_logger, init_state};
+use crate::model::notifications::DEFAULT_MAX_ATTEMPTS;
 use crate::Data;
 use canister_tracing_macros::trace;
 use ic_cdk_macros::init;
@@ -18,6 +19,10 @@ fn init(args: Args) {
         args.admins.into_iter().collect(),
         args.notification_method_name
             .unwrap_or_else(|| "notify_transaction".to_string()),
+        args.icrc_notification_method_name
+            .unwrap_or_else(|| "notify_icrc_transaction".to_string()),
+        args.max_notification_attempts
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS),
         args.test_mode,
     );
 
@@ -1,9 +1,11 @@
 use crate::env::CanisterEnv;
-use crate::lifecycle::{init_logger, init_state, UPGRADE_BUFFER_SIZE};
-use crate::{Data, LOG_MESSAGES};
+use crate::lifecycle::{init_logger, init_state, LEGACY_UPGRADE_BUFFER_SIZE};
+use crate::memory::{get_upgrades_memory, is_legacy_layout};
+use crate::{Data, DataPreviousVersion, LOG_MESSAGES};
 use canister_logger::{LogMessage, LogMessagesWrapper};
 use canister_tracing_macros::trace;
 use ic_cdk_macros::post_upgrade;
+use ic_stable_structures::reader::Reader;
 use stable_memory::deserialize_from_stable_memory;
 use tracing::info;
 use transaction_notifier::post_upgrade::Args;
@@ -15,8 +17,33 @@ fn post_upgrade(args: Args) {
 
     let env = Box::new(CanisterEnv::default());
 
-    let (data, log_messages, trace_messages): (Data, Vec<LogMessage>, Vec<LogMessage>) =
-        deserialize_from_stable_memory(UPGRADE_BUFFER_SIZE).unwrap();
+    let (mut data, log_messages, trace_messages) = if is_legacy_layout() {
+        // One-time migration from the previous layout, where everything was serialized directly
+        // into stable memory, into the stable memory backed structures
+        let (data, log_messages, trace_messages): (
+            DataPreviousVersion,
+            Vec<LogMessage>,
+            Vec<LogMessage>,
+        ) = deserialize_from_stable_memory(LEGACY_UPGRADE_BUFFER_SIZE).unwrap();
+
+        (Data::from(data), log_messages, trace_messages)
+    } else {
+        let memory = get_upgrades_memory();
+        let reader = Reader::new(&memory, 0);
+
+        let (mut data, log_messages, trace_messages): (Data, Vec<LogMessage>, Vec<LogMessage>) =
+            rmp_serde::from_read(reader).expect("Failed to deserialize state from stable memory");
+
+        data.tokens.migrate_from_symbol_keys();
+        data.notifications.migrate_from_single_queue();
+
+        (data, log_messages, trace_messages)
+    };
+
+    if let Some(max_notification_attempts) = args.max_notification_attempts {
+        data.notifications
+            .set_max_attempts(max_notification_attempts);
+    }
 
     init_logger(data.test_mode);
     init_state(env, data, args.wasm_version);
@@ -1,8 +1,8 @@
-use crate::lifecycle::UPGRADE_BUFFER_SIZE;
+use crate::memory::get_upgrades_memory;
 use crate::{take_state, LOG_MESSAGES};
 use canister_tracing_macros::trace;
 use ic_cdk_macros::pre_upgrade;
-use stable_memory::serialize_to_stable_memory;
+use ic_stable_structures::writer::Writer;
 use tracing::info;
 
 #[pre_upgrade]
@@ -17,5 +17,9 @@ fn pre_upgrade() {
     let trace_messages = messages_container.traces.drain_messages();
     let stable_state = (state.data, log_messages, trace_messages);
 
-    serialize_to_stable_memory(stable_state, UPGRADE_BUFFER_SIZE).unwrap();
+    let mut memory = get_upgrades_memory();
+    let mut writer = Writer::new(&mut memory, 0);
+
+    rmp_serde::encode::write(&mut writer, &stable_state)
+        .expect("Failed to serialize state to stable memory");
 }
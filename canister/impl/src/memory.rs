@@ -0,0 +1,112 @@
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::{DefaultMemoryImpl, Memory as MemoryTrait};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+const UPGRADES: MemoryId = MemoryId::new(0);
+// Tokens were originally keyed by symbol. They are now held in `TOKENS` keyed by ledger.
+const TOKENS_BY_SYMBOL: MemoryId = MemoryId::new(1);
+const SUBSCRIPTIONS: MemoryId = MemoryId::new(2);
+const SUBSCRIPTIONS_BY_CANISTER: MemoryId = MemoryId::new(3);
+// ICRC subscriptions were previously also indexed by account identifier, so that they matched
+// transactions on ledgers using the ICP interface. This is no longer written to.
+const ICRC_ACCOUNT_IDENTIFIERS: MemoryId = MemoryId::new(4);
+// Notifications were originally held in a single queue. They are now held in `NOTIFICATION_QUEUES`
+// keyed by subscriber.
+const NOTIFICATION_QUEUE: MemoryId = MemoryId::new(5);
+const TOKENS: MemoryId = MemoryId::new(6);
+const AUDIT_LOG: MemoryId = MemoryId::new(7);
+const NOTIFICATION_QUEUES: MemoryId = MemoryId::new(8);
+
+const WASM_PAGE_SIZE_BYTES: u64 = 64 * 1024;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+thread_local! {
+    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> =
+        MemoryManager::init(DefaultMemoryImpl::default());
+}
+
+pub fn get_upgrades_memory() -> Memory {
+    get_memory(UPGRADES)
+}
+
+pub fn get_tokens_memory() -> Memory {
+    get_memory(TOKENS)
+}
+
+pub fn get_tokens_by_symbol_memory() -> Memory {
+    get_memory(TOKENS_BY_SYMBOL)
+}
+
+pub fn get_subscriptions_memory() -> Memory {
+    get_memory(SUBSCRIPTIONS)
+}
+
+pub fn get_subscriptions_by_canister_memory() -> Memory {
+    get_memory(SUBSCRIPTIONS_BY_CANISTER)
+}
+
+pub fn get_icrc_account_identifiers_memory() -> Memory {
+    get_memory(ICRC_ACCOUNT_IDENTIFIERS)
+}
+
+pub fn get_notification_queue_memory() -> Memory {
+    get_memory(NOTIFICATION_QUEUE)
+}
+
+pub fn get_notification_queues_memory() -> Memory {
+    get_memory(NOTIFICATION_QUEUES)
+}
+
+pub fn get_audit_log_memory() -> Memory {
+    get_memory(AUDIT_LOG)
+}
+
+// Versions prior to the introduction of the memory manager serialized the whole of `Data` directly
+// into stable memory. This must be checked before the memory manager is first accessed, since
+// initializing the memory manager overwrites the start of stable memory.
+pub fn is_legacy_layout() -> bool {
+    let memory = DefaultMemoryImpl::default();
+    if memory.size() == 0 {
+        return false;
+    }
+
+    let mut magic = [0; 3];
+    memory.read(0, &mut magic);
+    &magic != b"MGR"
+}
+
+pub fn heap_memory_used() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE_BYTES
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+pub fn stable_memory_used() -> u64 {
+    DefaultMemoryImpl::default().size() * WASM_PAGE_SIZE_BYTES
+}
+
+// Virtual memories grow a page at a time and never shrink, so this is the high water mark of the
+// memory used by the structure held in it
+pub fn memory_used(memory: &Memory) -> u64 {
+    memory.size() * WASM_PAGE_SIZE_BYTES
+}
+
+pub fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    rmp_serde::to_vec(value).unwrap()
+}
+
+pub fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> T {
+    rmp_serde::from_slice(bytes).unwrap()
+}
+
+fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.get(id))
+}
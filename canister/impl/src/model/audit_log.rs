@@ -0,0 +1,70 @@
+use crate::memory::{deserialize, get_audit_log_memory, serialize, Memory};
+use candid::Principal;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use types::TimestampMillis;
+
+// An append-only record of the privileged calls made to the canister. Held in stable memory so that
+// it can grow without being serialized during upgrades. Entries are keyed by their id.
+pub struct AuditLog {
+    entries: StableBTreeMap<u64, AuditLogEntry, Memory>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub timestamp: TimestampMillis,
+    pub caller: Principal,
+    pub endpoint: String,
+    pub args: String,
+    pub response: String,
+}
+
+impl AuditLog {
+    pub fn push(&mut self, entry: AuditLogEntry) {
+        let id = self.entries.last_key_value().map_or(0, |(id, _)| id + 1);
+        self.entries.insert(id, entry);
+    }
+
+    pub fn iter_from(&self, id: u64) -> impl Iterator<Item = (u64, AuditLogEntry)> + '_ {
+        self.entries.range(id..)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries.len()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog {
+            entries: StableBTreeMap::init(get_audit_log_memory()),
+        }
+    }
+}
+
+impl Storable for AuditLogEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(serialize(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        deserialize(&bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl From<(u64, AuditLogEntry)> for transaction_notifier::AuditLogEntry {
+    fn from((id, entry): (u64, AuditLogEntry)) -> Self {
+        transaction_notifier::AuditLogEntry {
+            id,
+            timestamp: entry.timestamp,
+            caller: entry.caller,
+            endpoint: entry.endpoint,
+            args: entry.args,
+            response: entry.response,
+        }
+    }
+}
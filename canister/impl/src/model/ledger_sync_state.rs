@@ -1,9 +1,17 @@
+use crate::icp_block_hash::BlockHash;
 use ic_ledger_types::BlockIndex;
 use serde::{Deserialize, Serialize};
-use types::TimestampMillis;
+use std::cmp::min;
+use types::{Milliseconds, TimestampMillis};
 
 pub type Version = u32;
 
+pub const DEFAULT_SYNC_INTERVAL: Milliseconds = 5 * 1000; // 5 seconds
+const MAX_SYNC_DELAY_MULTIPLIER: u64 = 16;
+// Timestamps at or above this are in milliseconds, anything below it (which as milliseconds would
+// be before March 1973) must have been recorded in seconds
+const MIN_TIMESTAMP_MILLIS: TimestampMillis = 100_000_000_000;
+
 #[derive(Serialize, Deserialize)]
 pub struct LedgerSyncState {
     enabled: bool,
@@ -13,10 +21,34 @@ pub struct LedgerSyncState {
     last_successful_sync: TimestampMillis,
     last_failed_sync: TimestampMillis,
     version: Version,
+    // Hash of the block preceding `next_block_to_sync`, used to check that the next block links up
+    // with it. Only tracked for ledgers using the ICP interface.
+    #[serde(default)]
+    last_block_hash: Option<BlockHash>,
+    // Set if a block's parent hash doesn't match the hash of the block before it, in which case
+    // syncing is halted until the sync position is reset
+    #[serde(default)]
+    hash_chain_mismatch: Option<BlockIndex>,
+    #[serde(default = "default_sync_interval")]
+    sync_interval: Milliseconds,
+    // The delay before the next sync. This grows while syncs find no new blocks, up to
+    // `MAX_SYNC_DELAY_MULTIPLIER` times the sync interval.
+    #[serde(default = "default_sync_interval")]
+    sync_delay: Milliseconds,
+    // The ledger's chain length as of the last successful sync
+    #[serde(default)]
+    chain_length: Option<u64>,
+    // Timestamp of the block preceding `next_block_to_sync`
+    #[serde(default)]
+    last_block_timestamp: Option<TimestampMillis>,
+}
+
+fn default_sync_interval() -> Milliseconds {
+    DEFAULT_SYNC_INTERVAL
 }
 
 impl LedgerSyncState {
-    pub fn new(next_block_to_sync: BlockIndex) -> LedgerSyncState {
+    pub fn new(next_block_to_sync: BlockIndex, version: Version) -> LedgerSyncState {
         LedgerSyncState {
             enabled: false,
             in_progress: false,
@@ -24,7 +56,13 @@ impl LedgerSyncState {
             last_sync_started_at: 0,
             last_successful_sync: 0,
             last_failed_sync: 0,
-            version: 0,
+            version,
+            last_block_hash: None,
+            hash_chain_mismatch: None,
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+            sync_delay: DEFAULT_SYNC_INTERVAL,
+            chain_length: None,
+            last_block_timestamp: None,
         }
     }
 
@@ -39,6 +77,8 @@ impl LedgerSyncState {
     pub fn try_start(&mut self, now: TimestampMillis) -> TryStartSyncResult {
         if !self.enabled {
             TryStartSyncResult::Disabled
+        } else if self.hash_chain_mismatch.is_some() {
+            TryStartSyncResult::Halted
         } else if !self.in_progress {
             self.in_progress = true;
             self.last_sync_started_at = now;
@@ -48,30 +88,107 @@ impl LedgerSyncState {
         }
     }
 
-    pub fn mark_sync_complete(&mut self, success: bool, now: TimestampMillis) {
+    // Returns the delay before the next sync. If a full batch of blocks was returned then there
+    // may be more to sync so the next sync runs immediately, otherwise the delay doubles each time
+    // no new blocks are found.
+    pub fn mark_sync_complete(&mut self, result: SyncResult, now: TimestampMillis) -> Milliseconds {
         self.in_progress = false;
 
-        if success {
-            self.last_successful_sync = now;
-        } else {
+        if matches!(result, SyncResult::Failed) {
             self.last_failed_sync = now;
+        } else {
+            self.last_successful_sync = now;
         }
+
+        self.sync_delay = match result {
+            SyncResult::FullBatch => 0,
+            SyncResult::NewBlocks => self.sync_interval,
+            SyncResult::NoNewBlocks | SyncResult::Failed => min(
+                self.sync_delay.max(self.sync_interval).saturating_mul(2),
+                self.sync_interval.saturating_mul(MAX_SYNC_DELAY_MULTIPLIER),
+            ),
+        };
+        self.sync_delay
+    }
+
+    pub fn sync_interval(&self) -> Milliseconds {
+        self.sync_interval
+    }
+
+    pub fn set_sync_interval(&mut self, sync_interval: Milliseconds) {
+        self.sync_interval = sync_interval;
+        self.sync_delay = sync_interval;
+    }
+
+    pub fn sync_delay(&self) -> Milliseconds {
+        self.sync_delay
     }
 
     pub fn next_block_to_sync(&self) -> BlockIndex {
         self.next_block_to_sync
     }
 
+    // Ignored if the sync position has been reset since the sync started
     pub fn set_next_block_to_sync(
         &mut self,
         block_index: BlockIndex,
-        version_check: Option<Version>,
+        last_block_hash: Option<BlockHash>,
+        last_block_timestamp: Option<TimestampMillis>,
+        version: Version,
     ) {
-        if version_check.map_or(true, |v| v == self.version) {
+        if version == self.version {
             self.next_block_to_sync = block_index;
+            self.last_block_hash = last_block_hash;
+            self.last_block_timestamp = last_block_timestamp;
+        }
+    }
+
+    pub fn chain_length(&self) -> Option<u64> {
+        self.chain_length
+    }
+
+    pub fn set_chain_length(&mut self, chain_length: u64) {
+        self.chain_length = Some(chain_length);
+    }
+
+    pub fn last_block_timestamp(&self) -> Option<TimestampMillis> {
+        self.last_block_timestamp
+    }
+
+    // The number of blocks on the ledger which have yet to be synced
+    pub fn blocks_behind(&self) -> Option<u64> {
+        self.chain_length
+            .map(|length| length.saturating_sub(self.next_block_to_sync))
+    }
+
+    // Moves the sync position, after which the first block synced can't be checked against its
+    // parent since the hash of the parent is unknown
+    pub fn reset(&mut self, block_index: BlockIndex) {
+        self.next_block_to_sync = block_index;
+        self.last_block_hash = None;
+        self.last_block_timestamp = None;
+        self.hash_chain_mismatch = None;
+        self.version += 1;
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn last_block_hash(&self) -> Option<BlockHash> {
+        self.last_block_hash
+    }
+
+    pub fn mark_hash_chain_mismatch(&mut self, block_index: BlockIndex, version: Version) {
+        if version == self.version {
+            self.hash_chain_mismatch = Some(block_index);
         }
     }
 
+    pub fn hash_chain_mismatch(&self) -> Option<BlockIndex> {
+        self.hash_chain_mismatch
+    }
+
     pub fn last_sync_started_at(&self) -> TimestampMillis {
         self.last_sync_started_at
     }
@@ -84,13 +201,49 @@ impl LedgerSyncState {
         self.last_failed_sync
     }
 
-    pub fn incr_version(&mut self) {
-        self.version += 1;
+    // The original versions recorded these timestamps in seconds. Versions between then and the
+    // move to stable memory recorded them in milliseconds, so each is checked individually.
+    pub fn migrate_timestamps_to_millis(&mut self) {
+        for timestamp in [
+            &mut self.last_sync_started_at,
+            &mut self.last_successful_sync,
+            &mut self.last_failed_sync,
+        ] {
+            if *timestamp < MIN_TIMESTAMP_MILLIS {
+                *timestamp = timestamp.saturating_mul(1000);
+            }
+        }
     }
 }
 
+pub enum SyncResult {
+    FullBatch,
+    NewBlocks,
+    NoNewBlocks,
+    Failed,
+}
+
 pub enum TryStartSyncResult {
     Success(BlockIndex, Version),
     AlreadyInProgress,
     Disabled,
+    Halted,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_in_seconds_are_migrated_to_millis() {
+        let mut state = LedgerSyncState::new(0, 0);
+        state.last_sync_started_at = 1_660_000_000;
+        state.last_successful_sync = 1_660_000_000_000;
+
+        state.migrate_timestamps_to_millis();
+
+        assert_eq!(state.last_sync_started_at, 1_660_000_000_000);
+        assert_eq!(state.last_successful_sync, 1_660_000_000_000);
+        assert_eq!(state.last_failed_sync, 0);
+    }
 }
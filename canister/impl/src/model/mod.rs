@@ -1,4 +1,9 @@
+pub mod audit_log;
 pub mod ledger_sync_state;
+pub mod notification_queue;
 pub mod notifications;
+pub mod roles;
+pub mod subscriber_allowlist;
 pub mod subscriptions;
 pub mod token_data;
+pub mod tokens;
@@ -0,0 +1,138 @@
+use crate::memory::{get_notification_queue_memory, get_notification_queues_memory, Memory};
+use crate::model::notifications::Notification;
+use crate::model::subscriptions::{canister_key, CanisterKey, CANISTER_KEY_LEN};
+use ic_stable_structures::StableBTreeMap;
+use std::ops::{Bound, RangeInclusive};
+use types::CanisterId;
+
+const QUEUE_KEY_LEN: usize = CANISTER_KEY_LEN + 8;
+// Positions start in the middle of the key space so that entries can then be pushed onto either end
+// of a subscriber's queue
+pub const INITIAL_POSITION: u64 = u64::MAX / 2;
+
+// The subscriber's canister key followed by the entry's position within the subscriber's queue
+type QueueKey = [u8; QUEUE_KEY_LEN];
+
+// Holds a separate queue for each subscriber so that a subscriber with a large backlog doesn't hold
+// up the others. The entries are held in stable memory so that they don't need to be serialized
+// during upgrades, keyed such that each subscriber's queue can be read using a single range query.
+pub struct NotificationQueue {
+    queues: StableBTreeMap<QueueKey, Notification, Memory>,
+}
+
+impl NotificationQueue {
+    // The caller assigns positions from an increasing counter starting at `INITIAL_POSITION`
+    pub fn push_back(&mut self, notification: Notification, position: u64) {
+        let key = queue_key(&canister_key(&notification.canister_id), position);
+
+        self.queues.insert(key, notification);
+    }
+
+    // Positions below `INITIAL_POSITION` are only ever used by entries pushed onto the front
+    pub fn push_front(&mut self, notification: Notification) {
+        let position = match self.front(notification.canister_id) {
+            Some((key, _)) => position(&key) - 1,
+            None => INITIAL_POSITION - 1,
+        };
+        let key = queue_key(&canister_key(&notification.canister_id), position);
+
+        self.queues.insert(key, notification);
+    }
+
+    pub fn front(&self, canister_id: CanisterId) -> Option<(QueueKey, Notification)> {
+        self.queues
+            .range(subscriber_range(&canister_key(&canister_id)))
+            .next()
+    }
+
+    pub fn remove(&mut self, key: QueueKey) -> Option<Notification> {
+        self.queues.remove(&key)
+    }
+
+    // Returns the first subscriber with queued notifications after the given subscriber, wrapping
+    // around to the start once the end is reached
+    pub fn next_subscriber(&self, after: Option<CanisterId>) -> Option<CanisterId> {
+        let next = after.and_then(|canister_id| {
+            let end_of_queue = queue_key(&canister_key(&canister_id), u64::MAX);
+            self.queues
+                .range((Bound::Excluded(end_of_queue), Bound::Unbounded))
+                .next()
+        });
+
+        next.or_else(|| self.queues.first_key_value())
+            .map(|(_, notification)| notification.canister_id)
+    }
+
+    // Removes the notifications which match the predicate, returning them grouped by subscriber
+    // and in queue order within each subscriber
+    pub fn extract<F: Fn(&Notification) -> bool>(&mut self, predicate: F) -> Vec<Notification> {
+        let keys: Vec<_> = self
+            .queues
+            .iter()
+            .filter(|(_, n)| predicate(n))
+            .map(|(k, _)| k)
+            .collect();
+
+        self.remove_all(keys)
+    }
+
+    // Removes all of the subscriber's notifications, returning them in queue order
+    pub fn extract_subscriber(&mut self, canister_id: CanisterId) -> Vec<Notification> {
+        let keys: Vec<_> = self
+            .queues
+            .range(subscriber_range(&canister_key(&canister_id)))
+            .map(|(k, _)| k)
+            .collect();
+
+        self.remove_all(keys)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.queues.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    // Removes the notifications which were previously held in a single queue shared by all
+    // subscribers, returning them in queue order
+    pub fn take_single_queue() -> Vec<Notification> {
+        let mut single_queue: StableBTreeMap<u64, Notification, Memory> =
+            StableBTreeMap::init(get_notification_queue_memory());
+
+        let keys: Vec<_> = single_queue.iter().map(|(k, _)| k).collect();
+        keys.into_iter()
+            .filter_map(|k| single_queue.remove(&k))
+            .collect()
+    }
+
+    fn remove_all(&mut self, keys: Vec<QueueKey>) -> Vec<Notification> {
+        keys.into_iter()
+            .filter_map(|k| self.queues.remove(&k))
+            .collect()
+    }
+}
+
+impl Default for NotificationQueue {
+    fn default() -> Self {
+        NotificationQueue {
+            queues: StableBTreeMap::init(get_notification_queues_memory()),
+        }
+    }
+}
+
+fn queue_key(canister_key: &CanisterKey, position: u64) -> QueueKey {
+    let mut key = [0; QUEUE_KEY_LEN];
+    key[..CANISTER_KEY_LEN].copy_from_slice(canister_key);
+    key[CANISTER_KEY_LEN..].copy_from_slice(&position.to_be_bytes());
+    key
+}
+
+fn position(key: &QueueKey) -> u64 {
+    u64::from_be_bytes(key[CANISTER_KEY_LEN..].try_into().unwrap())
+}
+
+fn subscriber_range(canister_key: &CanisterKey) -> RangeInclusive<QueueKey> {
+    queue_key(canister_key, 0)..=queue_key(canister_key, u64::MAX)
+}
@@ -1,42 +1,1048 @@
+use crate::memory::{deserialize, serialize};
+use crate::model::notification_queue::{NotificationQueue, INITIAL_POSITION};
+use ic_cdk::api::call::RejectionCode;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use transaction_notifier::NotifyTransactionArgs;
-use types::CanisterId;
+use std::borrow::Cow;
+use std::cmp::min;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use transaction_notifier::{
+    DeadLetterFilter, DeadLetterSelection, NotificationArgs, NotifyIcrcTransactionArgs,
+    NotifyTransactionArgs, SubscriberStats,
+};
+use types::{CanisterId, TimestampMillis};
 
-#[derive(Serialize, Deserialize, Default)]
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+const RETRY_BASE_DELAY_MS: u64 = 1000; // 1 second
+const RETRY_MAX_DELAY_MS: u64 = 60 * 60 * 1000; // 1 hour
+const MAX_DEAD_LETTERS: usize = 10_000;
+const MAX_PULL_LOG_LEN: usize = 10_000;
+const NANOS_PER_MILLISECOND: u64 = 1_000_000;
+// The number of notifications which may be pushed to a subscriber concurrently. This must remain 1
+// for notifications to be delivered in order. Otherwise, if an earlier notification failed after a
+// later one was acknowledged, the retry of the earlier one would be discarded as already
+// acknowledged.
+const MAX_IN_FLIGHT_PER_SUBSCRIBER: usize = 1;
+
+// Serialized positionally as part of `Data`, so new fields must likewise be appended at the end and
+// marked `#[serde(default)]`.
+#[derive(Serialize, Deserialize)]
 pub struct Notifications {
-    queue: VecDeque<Notification>,
+    // The queue is held in stable memory so is not serialized during upgrades
+    #[serde(skip)]
+    queue: NotificationQueue,
     total_sent: u64,
+    retries: BTreeMap<TimestampMillis, Vec<Notification>>,
+    max_attempts: u32,
+    dead_letters: VecDeque<DeadLetter>,
+    next_dead_letter_id: u64,
+    // Subscribers which have opted in to pulling their notifications rather than having them
+    // pushed. Their notifications are retained here until they are acknowledged.
+    pull_logs: HashMap<CanisterId, PullLog>,
+    subscribers: HashMap<CanisterId, SubscriberState>,
+    // The number of failed push attempts, including those which were later retried successfully
+    #[serde(default)]
+    total_failed: u64,
+    // Notifications pushed onto the back of a subscriber's queue take their position from this
+    // counter, so positions only ever increase within each queue
+    #[serde(default = "initial_queue_position")]
+    next_queue_position: u64,
+    // The subscriber most recently given a notification to push, the next round starts after it
+    #[serde(default)]
+    last_served: Option<CanisterId>,
+    // The number of notifications currently being pushed to each subscriber, which is limited to
+    // `MAX_IN_FLIGHT_PER_SUBSCRIBER`
+    #[serde(skip)]
+    in_flight: HashMap<CanisterId, usize>,
 }
 
 impl Notifications {
-    pub fn enqueue(&mut self, notification: Notification) {
-        self.queue.push_back(notification);
+    pub fn new(max_attempts: u32) -> Notifications {
+        Notifications {
+            queue: NotificationQueue::default(),
+            total_sent: 0,
+            retries: BTreeMap::new(),
+            max_attempts,
+            dead_letters: VecDeque::new(),
+            next_dead_letter_id: 0,
+            pull_logs: HashMap::new(),
+            subscribers: HashMap::new(),
+            total_failed: 0,
+            next_queue_position: INITIAL_POSITION,
+            last_served: None,
+            in_flight: HashMap::new(),
+        }
+    }
+
+    // Assigns the notification the next id for its subscriber then either queues it to be pushed
+    // or appends it to the subscriber's pull log. Notifications for blocks which the subscriber has
+    // already been notified of (eg. because a token's sync position was rewound) are skipped.
+    pub fn enqueue(&mut self, notification: Notification, now: TimestampMillis) {
+        let subscriber = self
+            .subscribers
+            .entry(notification.canister_id)
+            .or_default();
+        let ledger_canister_id = notification.args.ledger_canister_id();
+        let block_index = notification.args.block_index();
+
+        if subscriber
+            .last_block_indexes
+            .get(&ledger_canister_id)
+            .map_or(false, |b| block_index <= *b)
+        {
+            return;
+        }
+        subscriber
+            .last_block_indexes
+            .insert(ledger_canister_id, block_index);
+
+        self.enqueue_with_new_id(notification, now);
+    }
+
+    fn enqueue_with_new_id(&mut self, mut notification: Notification, now: TimestampMillis) {
+        let subscriber = self
+            .subscribers
+            .entry(notification.canister_id)
+            .or_default();
+        let id = subscriber.next_notification_id;
+        subscriber.next_notification_id += 1;
+
+        notification.args.set_notification_id(id);
+
+        if self.pull_logs.contains_key(&notification.canister_id) {
+            self.push_to_pull_log(notification, now);
+        } else {
+            self.push_back(notification);
+        }
+    }
+
+    // If the subscriber's pull log is full its oldest notification is moved into the dead letter
+    // store, from where it can be replayed once the subscriber has caught up
+    fn push_to_pull_log(&mut self, notification: Notification, now: TimestampMillis) {
+        let canister_id = notification.canister_id;
+        let evicted = self
+            .pull_logs
+            .get_mut(&canister_id)
+            .and_then(|log| log.push(notification.args));
+
+        if let Some(args) = evicted {
+            self.add_dead_letter(
+                Notification {
+                    canister_id,
+                    args,
+                    attempts: 0,
+                },
+                "Pull log full".to_string(),
+                now,
+            );
+        }
+    }
+
+    fn push_back(&mut self, notification: Notification) {
+        let position = self.next_queue_position;
+        self.next_queue_position += 1;
+
+        self.queue.push_back(notification, position);
+    }
+
+    // Takes up to `max_count` notifications to be pushed, with at most
+    // `MAX_IN_FLIGHT_PER_SUBSCRIBER` in flight per subscriber. Subscribers are served in turn,
+    // starting after the subscriber served last, so that those with a large backlog don't starve
+    // the others. Notifications are taken from the front of each subscriber's queue, and
+    // subscribers which have a notification awaiting a retry are skipped so that their
+    // notifications are delivered in order.
+    pub fn next_batch(&mut self, max_count: usize) -> Vec<Notification> {
+        let blocked: HashSet<_> = self
+            .retries
+            .values()
+            .flatten()
+            .map(|n| n.canister_id)
+            .collect();
+
+        let mut batch = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = self.last_served;
+
+        while batch.len() < max_count {
+            let canister_id = match self.queue.next_subscriber(current) {
+                Some(canister_id) if visited.insert(canister_id) => canister_id,
+                _ => break,
+            };
+            current = Some(canister_id);
+
+            if blocked.contains(&canister_id) {
+                continue;
+            }
+            let in_flight = self
+                .in_flight
+                .get(&canister_id)
+                .copied()
+                .unwrap_or_default();
+            for _ in in_flight..MAX_IN_FLIGHT_PER_SUBSCRIBER {
+                if batch.len() >= max_count {
+                    break;
+                }
+                if let Some(notification) = self.pop_unacknowledged(canister_id) {
+                    *self.in_flight.entry(canister_id).or_default() += 1;
+                    self.last_served = Some(canister_id);
+                    batch.push(notification);
+                } else {
+                    break;
+                }
+            }
+        }
+        batch
     }
 
-    pub fn dequeue(&mut self) -> Option<Notification> {
-        self.queue.pop_front()
+    // Takes the notification at the front of the subscriber's queue, discarding any which the
+    // subscriber has already acknowledged
+    fn pop_unacknowledged(&mut self, canister_id: CanisterId) -> Option<Notification> {
+        while let Some((key, notification)) = self.queue.front(canister_id) {
+            self.queue.remove(key);
+            if !self.is_acknowledged(&notification) {
+                return Some(notification);
+            }
+        }
+        None
     }
 
-    pub fn mark_sent(&mut self) {
+    pub fn mark_sent(&mut self, notification: &Notification, now: TimestampMillis) {
+        let canister_id = notification.canister_id;
+        let block_timestamp = notification.args.timestamp_nanos() / NANOS_PER_MILLISECOND;
+
+        self.end_in_flight(canister_id);
+        self.acknowledge(canister_id, notification.args.notification_id());
         self.total_sent += 1;
+
+        let stats = &mut self.subscribers.entry(canister_id).or_default().stats;
+        stats.delivered += 1;
+        stats.total_latency += now.saturating_sub(block_timestamp);
+        stats.last_success = Some(now);
+    }
+
+    fn end_in_flight(&mut self, canister_id: CanisterId) {
+        if let Entry::Occupied(mut e) = self.in_flight.entry(canister_id) {
+            *e.get_mut() -= 1;
+            if *e.get() == 0 {
+                e.remove();
+            }
+        }
+    }
+
+    // Schedules the notification to be retried after an exponentially increasing delay. If the
+    // notification has already been attempted the maximum number of times it is moved into the
+    // dead letter store, evicting the oldest dead letter if the store is full.
+    pub fn mark_failed(
+        &mut self,
+        mut notification: Notification,
+        error: &(RejectionCode, String),
+        now: TimestampMillis,
+    ) -> MarkFailedResult {
+        self.end_in_flight(notification.canister_id);
+        self.total_failed += 1;
+        notification.attempts += 1;
+
+        let stats = &mut self
+            .subscribers
+            .entry(notification.canister_id)
+            .or_default()
+            .stats;
+        stats.failed += 1;
+        stats.last_failure = Some(now);
+        stats.last_error = Some((error.0 as i32, error.1.clone()));
+
+        if notification.attempts >= self.max_attempts {
+            stats.dead_lettered += 1;
+
+            let id = self.add_dead_letter(notification, format!("{error:?}"), now);
+            MarkFailedResult::DeadLettered(id)
+        } else {
+            stats.retried += 1;
+            let retry_at = now + retry_delay(notification.attempts);
+            self.retries.entry(retry_at).or_default().push(notification);
+            MarkFailedResult::RetryScheduled(retry_at)
+        }
+    }
+
+    fn add_dead_letter(
+        &mut self,
+        notification: Notification,
+        last_error: String,
+        now: TimestampMillis,
+    ) -> u64 {
+        if self.dead_letters.len() >= MAX_DEAD_LETTERS {
+            self.dead_letters.pop_front();
+        }
+
+        let id = self.next_dead_letter_id;
+        self.next_dead_letter_id += 1;
+
+        self.dead_letters.push_back(DeadLetter {
+            id,
+            notification,
+            failed_at: now,
+            last_error,
+        });
+        id
+    }
+
+    // Moves any notifications whose retry time has passed back onto the front of their subscribers'
+    // queues so that they are pushed before any later notifications for the same subscribers.
+    pub fn requeue_due_retries(&mut self, now: TimestampMillis) {
+        let not_yet_due = self.retries.split_off(&(now + 1));
+        let due = std::mem::replace(&mut self.retries, not_yet_due);
+
+        for notification in due.into_values().flatten().rev() {
+            self.queue.push_front(notification);
+        }
+    }
+
+    pub fn next_retry_at(&self) -> Option<TimestampMillis> {
+        self.retries.keys().next().copied()
+    }
+
+    pub fn dead_letters(&self) -> impl Iterator<Item = &DeadLetter> {
+        self.dead_letters.iter()
+    }
+
+    // Removes the matching dead letters and queues their notifications again with their attempt
+    // counters reset. Each is given a new id since the subscriber may have acknowledged later ids.
+    pub fn replay_dead_letters<F: Fn(&DeadLetter) -> bool>(
+        &mut self,
+        predicate: F,
+        now: TimestampMillis,
+    ) -> usize {
+        let (to_replay, to_keep) = std::mem::take(&mut self.dead_letters)
+            .into_iter()
+            .partition::<VecDeque<_>, _>(predicate);
+
+        self.dead_letters = to_keep;
+
+        let count = to_replay.len();
+        for dead_letter in to_replay {
+            let mut notification = dead_letter.notification;
+            notification.attempts = 0;
+            self.enqueue_with_new_id(notification, now);
+        }
+        count
+    }
+
+    pub fn purge_dead_letters<F: Fn(&DeadLetter) -> bool>(&mut self, predicate: F) -> usize {
+        let count_before = self.dead_letters.len();
+        self.dead_letters.retain(|d| !predicate(d));
+        count_before - self.dead_letters.len()
+    }
+
+    // Removes the notifications for the given ledger which are waiting to be pushed, including
+    // those awaiting a retry. Notifications already in pull logs or dead letters are left as is.
+    pub fn purge_ledger(&mut self, ledger_canister_id: CanisterId) -> usize {
+        let mut count = self
+            .queue
+            .extract(|n| n.args.ledger_canister_id() == ledger_canister_id)
+            .len();
+
+        for notifications in self.retries.values_mut() {
+            let count_before = notifications.len();
+            notifications.retain(|n| n.args.ledger_canister_id() != ledger_canister_id);
+            count += count_before - notifications.len();
+        }
+        self.retries.retain(|_, n| !n.is_empty());
+
+        count
+    }
+
+    // Switches the subscriber to pull mode, moving any of its notifications which are waiting to
+    // be pushed (including those awaiting a retry) into its pull log.
+    pub fn enable_pull_mode(&mut self, canister_id: CanisterId, now: TimestampMillis) {
+        if self.pull_logs.contains_key(&canister_id) {
+            return;
+        }
+
+        let mut to_pull = self.queue.extract_subscriber(canister_id);
+
+        for notifications in self.retries.values_mut() {
+            let (pulled, kept) = std::mem::take(notifications)
+                .into_iter()
+                .partition::<Vec<_>, _>(|n| n.canister_id == canister_id);
+
+            *notifications = kept;
+            to_pull.extend(pulled);
+        }
+        self.retries.retain(|_, n| !n.is_empty());
+
+        to_pull.sort_unstable_by_key(|n| n.args.notification_id());
+
+        self.pull_logs.insert(canister_id, PullLog::default());
+        for notification in to_pull {
+            self.push_to_pull_log(notification, now);
+        }
+    }
+
+    // Switches the subscriber back to push mode. Any notifications it has not yet acknowledged are
+    // queued to be pushed to it.
+    pub fn disable_pull_mode(&mut self, canister_id: CanisterId) {
+        if let Some(log) = self.pull_logs.remove(&canister_id) {
+            for args in log.notifications {
+                self.push_back(Notification {
+                    canister_id,
+                    args,
+                    attempts: 0,
+                });
+            }
+        }
+    }
+
+    // Acknowledges all notifications up to and including `since_id` then returns up to
+    // `max_results` of the notifications which follow it. Returns None if the subscriber is not in
+    // pull mode.
+    pub fn pull(
+        &mut self,
+        canister_id: &CanisterId,
+        since_id: Option<u64>,
+        max_results: usize,
+    ) -> Option<Vec<NotificationArgs>> {
+        if let Some(since_id) = since_id {
+            self.pull_logs.get_mut(canister_id)?.acknowledge(since_id);
+            self.acknowledge(*canister_id, since_id);
+        }
+
+        let log = self.pull_logs.get(canister_id)?;
+
+        Some(
+            log.notifications
+                .iter()
+                .take(max_results)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn last_acknowledged_id(&self, canister_id: &CanisterId) -> Option<u64> {
+        self.subscribers
+            .get(canister_id)
+            .and_then(|s| s.last_acknowledged_id)
+    }
+
+    fn acknowledge(&mut self, canister_id: CanisterId, notification_id: u64) {
+        let subscriber = self.subscribers.entry(canister_id).or_default();
+
+        if subscriber
+            .last_acknowledged_id
+            .map_or(true, |id| notification_id > id)
+        {
+            subscriber.last_acknowledged_id = Some(notification_id);
+        }
+    }
+
+    fn is_acknowledged(&self, notification: &Notification) -> bool {
+        self.last_acknowledged_id(&notification.canister_id)
+            .map_or(false, |id| notification.args.notification_id() <= id)
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: u32) {
+        self.max_attempts = max_attempts;
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
     }
 
     pub fn total_sent(&self) -> u64 {
         self.total_sent
     }
 
-    pub fn queue_len(&self) -> usize {
+    pub fn subscriber_stats(&self, canister_id: &CanisterId) -> Option<SubscriberStats> {
+        self.subscribers
+            .get(canister_id)
+            .map(|s| s.stats.to_subscriber_stats(*canister_id))
+    }
+
+    pub fn all_subscriber_stats(&self) -> Vec<SubscriberStats> {
+        let mut stats: Vec<_> = self
+            .subscribers
+            .iter()
+            .map(|(canister_id, s)| s.stats.to_subscriber_stats(*canister_id))
+            .collect();
+
+        stats.sort_unstable_by_key(|s| s.canister_id);
+        stats
+    }
+
+    pub fn total_failed(&self) -> u64 {
+        self.total_failed
+    }
+
+    pub fn total_retried(&self) -> u64 {
+        self.subscribers.values().map(|s| s.stats.retried).sum()
+    }
+
+    pub fn failing_subscriber_count(&self) -> usize {
+        self.subscribers
+            .values()
+            .filter(|s| s.stats.last_failure > s.stats.last_success)
+            .count()
+    }
+
+    pub fn queue_len(&self) -> u64 {
         self.queue.len()
     }
 
     pub fn is_queue_empty(&self) -> bool {
         self.queue.is_empty()
     }
+
+    pub fn pending_retry_count(&self) -> usize {
+        self.retries.values().map(|n| n.len()).sum()
+    }
+
+    pub fn dead_letter_count(&self) -> usize {
+        self.dead_letters.len()
+    }
+
+    // One-time migration of the notifications which were previously held in a single queue shared
+    // by all subscribers
+    pub fn migrate_from_single_queue(&mut self) {
+        for notification in NotificationQueue::take_single_queue() {
+            self.push_back(notification);
+        }
+    }
+
+    pub fn pull_subscriber_count(&self) -> usize {
+        self.pull_logs.len()
+    }
+
+    pub fn pending_pull_count(&self) -> usize {
+        self.pull_logs.values().map(|l| l.notifications.len()).sum()
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+// The layout in which notifications were serialized during upgrades before the queue was moved into
+// stable memory
+#[derive(Deserialize)]
+pub struct NotificationsPreviousVersion {
+    queue: VecDeque<Notification>,
+    total_sent: u64,
+    #[serde(default)]
+    retries: BTreeMap<TimestampMillis, Vec<Notification>>,
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+    #[serde(default)]
+    dead_letters: VecDeque<DeadLetter>,
+    #[serde(default)]
+    next_dead_letter_id: u64,
+    #[serde(default)]
+    pull_logs: HashMap<CanisterId, PullLog>,
+    #[serde(default)]
+    subscribers: HashMap<CanisterId, SubscriberState>,
+}
+
+impl NotificationsPreviousVersion {
+    // Notifications queued before ids were introduced all have an id of 0, so this gives each of
+    // them an id, in the order in which they will be pushed
+    fn assign_ids_to_legacy_notifications(&mut self) {
+        if !self.subscribers.is_empty() {
+            return;
+        }
+
+        let retries = self.retries.values_mut().flatten();
+        let dead_letters = self.dead_letters.iter_mut().map(|d| &mut d.notification);
+
+        for notification in retries.chain(self.queue.iter_mut()).chain(dead_letters) {
+            let subscriber = self
+                .subscribers
+                .entry(notification.canister_id)
+                .or_default();
+            notification
+                .args
+                .set_notification_id(subscriber.next_notification_id);
+            subscriber.next_notification_id += 1;
+        }
+    }
+}
+
+impl From<NotificationsPreviousVersion> for Notifications {
+    fn from(mut previous: NotificationsPreviousVersion) -> Self {
+        previous.assign_ids_to_legacy_notifications();
+
+        let mut notifications = Notifications {
+            queue: NotificationQueue::default(),
+            total_sent: previous.total_sent,
+            retries: previous.retries,
+            max_attempts: previous.max_attempts,
+            dead_letters: previous.dead_letters,
+            next_dead_letter_id: previous.next_dead_letter_id,
+            pull_logs: previous.pull_logs,
+            subscribers: previous.subscribers,
+            total_failed: 0,
+            next_queue_position: INITIAL_POSITION,
+            last_served: None,
+            in_flight: HashMap::new(),
+        };
+
+        for notification in previous.queue {
+            notifications.push_back(notification);
+        }
+        notifications
+    }
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Notifications::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "NotificationCombined", into = "NotificationCombined")]
 pub struct Notification {
     pub canister_id: CanisterId,
-    pub args: NotifyTransactionArgs,
+    pub args: NotificationArgs,
+    pub attempts: u32,
+}
+
+// Notifications are serialized in this shape so that those which were serialized before ICRC
+// ledgers were supported (when `args` could only be `NotifyTransactionArgs`) can still be read.
+#[derive(Serialize, Deserialize)]
+struct NotificationCombined {
+    canister_id: CanisterId,
+    args: Option<NotifyTransactionArgs>,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(default)]
+    icrc_args: Option<NotifyIcrcTransactionArgs>,
+}
+
+impl Storable for Notification {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(serialize(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        deserialize(&bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl TryFrom<NotificationCombined> for Notification {
+    type Error = String;
+
+    fn try_from(n: NotificationCombined) -> Result<Self, Self::Error> {
+        let args = match (n.args, n.icrc_args) {
+            (_, Some(icrc_args)) => NotificationArgs::Icrc(icrc_args),
+            (Some(args), None) => NotificationArgs::Icp(args),
+            (None, None) => return Err("Notification has no args".to_string()),
+        };
+
+        Ok(Notification {
+            canister_id: n.canister_id,
+            args,
+            attempts: n.attempts,
+        })
+    }
+}
+
+impl From<Notification> for NotificationCombined {
+    fn from(n: Notification) -> Self {
+        let (args, icrc_args) = match n.args {
+            NotificationArgs::Icp(args) => (Some(args), None),
+            NotificationArgs::Icrc(icrc_args) => (None, Some(icrc_args)),
+        };
+
+        NotificationCombined {
+            canister_id: n.canister_id,
+            args,
+            attempts: n.attempts,
+            icrc_args,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub notification: Notification,
+    pub failed_at: TimestampMillis,
+    pub last_error: String,
+}
+
+impl DeadLetter {
+    pub fn matches(&self, filter: &DeadLetterFilter) -> bool {
+        let notification = &self.notification;
+        let block_index = notification.args.block_index();
+
+        filter
+            .canister_id
+            .map_or(true, |c| c == notification.canister_id)
+            && filter
+                .ledger_canister_id
+                .map_or(true, |l| l == notification.args.ledger_canister_id())
+            && filter.from_block_index.map_or(true, |b| block_index >= b)
+            && filter.to_block_index.map_or(true, |b| block_index <= b)
+    }
+
+    pub fn is_selected(&self, selection: &DeadLetterSelection) -> bool {
+        match selection {
+            DeadLetterSelection::Ids(ids) => ids.contains(&self.id),
+            DeadLetterSelection::Filter(filter) => self.matches(filter),
+        }
+    }
+}
+
+impl From<&DeadLetter> for transaction_notifier::DeadLetter {
+    fn from(dead_letter: &DeadLetter) -> Self {
+        transaction_notifier::DeadLetter {
+            id: dead_letter.id,
+            canister_id: dead_letter.notification.canister_id,
+            args: dead_letter.notification.args.clone(),
+            attempts: dead_letter.notification.attempts,
+            failed_at: dead_letter.failed_at,
+            last_error: dead_letter.last_error.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SubscriberState {
+    next_notification_id: u64,
+    last_acknowledged_id: Option<u64>,
+    // The latest block index the subscriber has been notified of for each ledger
+    last_block_indexes: HashMap<CanisterId, u64>,
+    #[serde(default)]
+    stats: DeliveryStats,
+}
+
+// Only covers notifications which are pushed, since pulled notifications are fetched by the
+// subscriber itself
+#[derive(Serialize, Deserialize, Default)]
+struct DeliveryStats {
+    delivered: u64,
+    failed: u64,
+    retried: u64,
+    dead_lettered: u64,
+    last_success: Option<TimestampMillis>,
+    last_failure: Option<TimestampMillis>,
+    last_error: Option<(i32, String)>,
+    // The sum of the latencies of the delivered notifications, used to calculate the average
+    total_latency: u64,
+}
+
+impl DeliveryStats {
+    fn to_subscriber_stats(&self, canister_id: CanisterId) -> SubscriberStats {
+        SubscriberStats {
+            canister_id,
+            delivered: self.delivered,
+            failed: self.failed,
+            retried: self.retried,
+            dead_lettered: self.dead_lettered,
+            last_success: self.last_success,
+            last_failure: self.last_failure,
+            last_error_code: self.last_error.as_ref().map(|(code, _)| *code),
+            last_error_message: self.last_error.as_ref().map(|(_, message)| message.clone()),
+            average_latency_ms: self.total_latency.checked_div(self.delivered),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PullLog {
+    notifications: VecDeque<NotificationArgs>,
+}
+
+impl PullLog {
+    // If the log is full the oldest notification is evicted and returned
+    fn push(&mut self, args: NotificationArgs) -> Option<NotificationArgs> {
+        let evicted = if self.notifications.len() >= MAX_PULL_LOG_LEN {
+            self.notifications.pop_front()
+        } else {
+            None
+        };
+        self.notifications.push_back(args);
+        evicted
+    }
+
+    fn acknowledge(&mut self, up_to_id: u64) {
+        while matches!(self.notifications.front(), Some(n) if n.notification_id() <= up_to_id) {
+            self.notifications.pop_front();
+        }
+    }
+}
+
+pub enum MarkFailedResult {
+    RetryScheduled(TimestampMillis),
+    DeadLettered(u64),
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    let multiplier = 1u64
+        .checked_shl(attempts.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+
+    min(
+        RETRY_BASE_DELAY_MS.saturating_mul(multiplier),
+        RETRY_MAX_DELAY_MS,
+    )
+}
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+fn initial_queue_position() -> u64 {
+    INITIAL_POSITION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use ic_ledger_types::{Block, Memo, Timestamp, Transaction};
+
+    #[test]
+    fn failed_notification_is_retried_with_backoff_then_dead_lettered() {
+        let mut notifications = Notifications::new(3);
+        let mut now = 1_000_000;
+
+        notifications.enqueue(notification(1), now);
+        let notification = notifications.next_batch(5).pop().unwrap();
+
+        let result = notifications.mark_failed(notification, &error(), now);
+        assert!(matches!(result, MarkFailedResult::RetryScheduled(t) if t == now + 1000));
+
+        notifications.requeue_due_retries(now + 999);
+        assert!(notifications.is_queue_empty());
+
+        now += 1000;
+        notifications.requeue_due_retries(now);
+        let notification = notifications.next_batch(5).pop().unwrap();
+        assert_eq!(notification.attempts, 1);
+
+        let result = notifications.mark_failed(notification, &error(), now);
+        assert!(matches!(result, MarkFailedResult::RetryScheduled(t) if t == now + 2000));
+
+        now += 2000;
+        notifications.requeue_due_retries(now);
+        let notification = notifications.next_batch(5).pop().unwrap();
+
+        let result = notifications.mark_failed(notification, &error(), now);
+        assert!(matches!(result, MarkFailedResult::DeadLettered(0)));
+        assert_eq!(notifications.dead_letter_count(), 1);
+        assert_eq!(notifications.pending_retry_count(), 0);
+
+        assert_eq!(notifications.replay_dead_letters(|d| d.id == 0, now), 1);
+        assert_eq!(notifications.dead_letter_count(), 0);
+
+        let notification = notifications.next_batch(5).pop().unwrap();
+        assert_eq!(notification.attempts, 0);
+        assert_eq!(notification.args.notification_id(), 1);
+    }
+
+    #[test]
+    fn notifications_are_pushed_in_order_one_at_a_time_per_subscriber() {
+        let mut notifications = Notifications::new(3);
+
+        notifications.enqueue(notification(1), 0);
+        notifications.enqueue(notification(2), 0);
+        // Already notified of this block so it is skipped
+        notifications.enqueue(notification(2), 0);
+
+        let batch = notifications.next_batch(5);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].args.notification_id(), 0);
+        assert!(notifications.next_batch(5).is_empty());
+
+        notifications.mark_sent(&batch[0], 0);
+
+        let batch = notifications.next_batch(5);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].args.notification_id(), 1);
+        assert_eq!(batch[0].args.block_index(), 2);
+
+        notifications.mark_sent(&batch[0], 0);
+        assert!(notifications.is_queue_empty());
+        assert_eq!(
+            notifications.last_acknowledged_id(&Principal::anonymous()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn delivery_stats_are_tracked_per_subscriber() {
+        let mut notifications = Notifications::new(2);
+        let now = 1_000_000;
+
+        notifications.enqueue(notification(1), now);
+        let notification = notifications.next_batch(5).pop().unwrap();
+        notifications.mark_failed(notification, &error(), now);
+        assert_eq!(notifications.failing_subscriber_count(), 1);
+
+        notifications.requeue_due_retries(now + 1000);
+        let notification = notifications.next_batch(5).pop().unwrap();
+        notifications.mark_sent(&notification, now + 1000);
+        assert_eq!(notifications.failing_subscriber_count(), 0);
+        assert_eq!(notifications.total_retried(), 1);
+
+        let stats = notifications
+            .subscriber_stats(&Principal::anonymous())
+            .unwrap();
+        assert_eq!(stats.delivered, 1);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.retried, 1);
+        assert_eq!(stats.dead_lettered, 0);
+        assert_eq!(stats.last_success, Some(now + 1000));
+        assert_eq!(stats.last_failure, Some(now));
+        assert_eq!(
+            stats.last_error_code,
+            Some(RejectionCode::CanisterError as i32)
+        );
+        assert_eq!(stats.average_latency_ms, Some(now + 1000));
+    }
+
+    #[test]
+    fn subscribers_are_served_in_turn() {
+        let mut notifications = Notifications::new(3);
+        let busy = Principal::from_slice(&[1]);
+        let quiet = Principal::from_slice(&[2]);
+
+        for block_index in 1..=3 {
+            notifications.enqueue(notification_for(busy, block_index), 0);
+        }
+        notifications.enqueue(notification_for(quiet, 4), 0);
+
+        let batch = notifications.next_batch(1);
+        assert_eq!(batch[0].canister_id, busy);
+        notifications.mark_sent(&batch[0], 0);
+
+        // The busy subscriber has more queued but the quiet one is next in turn
+        let batch = notifications.next_batch(1);
+        assert_eq!(batch[0].canister_id, quiet);
+        notifications.mark_sent(&batch[0], 0);
+
+        let batch = notifications.next_batch(5);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].canister_id, busy);
+        assert_eq!(batch[0].args.block_index(), 2);
+    }
+
+    #[test]
+    fn notifications_serialized_without_total_failed_can_be_read() {
+        // The layout prior to failed push attempts being counted
+        #[derive(Serialize)]
+        struct NotificationsWithoutTotalFailed {
+            total_sent: u64,
+            retries: BTreeMap<TimestampMillis, Vec<Notification>>,
+            max_attempts: u32,
+            dead_letters: VecDeque<DeadLetter>,
+            next_dead_letter_id: u64,
+            pull_logs: HashMap<CanisterId, PullLog>,
+            subscribers: HashMap<CanisterId, SubscriberState>,
+        }
+
+        let bytes = serialize(&NotificationsWithoutTotalFailed {
+            total_sent: 5,
+            retries: BTreeMap::new(),
+            max_attempts: 3,
+            dead_letters: VecDeque::new(),
+            next_dead_letter_id: 1,
+            pull_logs: HashMap::new(),
+            subscribers: HashMap::new(),
+        });
+        let notifications: Notifications = deserialize(&bytes);
+
+        assert_eq!(notifications.total_sent(), 5);
+        assert_eq!(notifications.total_failed(), 0);
+        assert_eq!(notifications.max_attempts(), 3);
+        assert_eq!(notifications.next_dead_letter_id, 1);
+    }
+
+    #[test]
+    fn notifications_serialized_without_queue_positions_can_be_read() {
+        // The layout prior to the per subscriber queues
+        #[derive(Serialize)]
+        struct NotificationsWithoutQueuePositions {
+            total_sent: u64,
+            retries: BTreeMap<TimestampMillis, Vec<Notification>>,
+            max_attempts: u32,
+            dead_letters: VecDeque<DeadLetter>,
+            next_dead_letter_id: u64,
+            pull_logs: HashMap<CanisterId, PullLog>,
+            subscribers: HashMap<CanisterId, SubscriberState>,
+            total_failed: u64,
+        }
+
+        let bytes = serialize(&NotificationsWithoutQueuePositions {
+            total_sent: 5,
+            retries: BTreeMap::new(),
+            max_attempts: 3,
+            dead_letters: VecDeque::new(),
+            next_dead_letter_id: 1,
+            pull_logs: HashMap::new(),
+            subscribers: HashMap::new(),
+            total_failed: 2,
+        });
+        let mut notifications: Notifications = deserialize(&bytes);
+
+        assert_eq!(notifications.total_sent(), 5);
+        assert_eq!(notifications.total_failed(), 2);
+        assert_eq!(notifications.max_attempts(), 3);
+        assert_eq!(notifications.next_queue_position, INITIAL_POSITION);
+        assert_eq!(notifications.last_served, None);
+
+        // The queue position and last subscriber served are preserved across upgrades
+        notifications.enqueue(notification(1), 0);
+        notifications.next_batch(5);
+        let notifications: Notifications = deserialize(&serialize(&notifications));
+
+        assert_eq!(notifications.next_queue_position, INITIAL_POSITION + 1);
+        assert_eq!(notifications.last_served, Some(Principal::anonymous()));
+    }
+
+    #[test]
+    fn pull_log_overflow_is_moved_to_dead_letters() {
+        let mut notifications = Notifications::new(3);
+        let now = 1_000_000;
+
+        notifications.enable_pull_mode(Principal::anonymous(), now);
+        for block_index in 0..=(MAX_PULL_LOG_LEN as u64) {
+            notifications.enqueue(notification(block_index), now);
+        }
+
+        assert_eq!(notifications.pending_pull_count(), MAX_PULL_LOG_LEN);
+        let dead_letters: Vec<_> = notifications.dead_letters().collect();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].notification.args.block_index(), 0);
+        assert_eq!(dead_letters[0].failed_at, now);
+    }
+
+    fn error() -> (RejectionCode, String) {
+        (RejectionCode::CanisterError, "error".to_string())
+    }
+
+    fn notification(block_index: u64) -> Notification {
+        notification_for(Principal::anonymous(), block_index)
+    }
+
+    fn notification_for(canister_id: CanisterId, block_index: u64) -> Notification {
+        Notification {
+            canister_id,
+            args: NotificationArgs::Icp(NotifyTransactionArgs {
+                token_symbol: "ICP".to_string(),
+                ledger_canister_id: Principal::anonymous(),
+                block_index,
+                block: Block {
+                    parent_hash: None,
+                    transaction: Transaction {
+                        memo: Memo(0),
+                        operation: None,
+                        created_at_time: Timestamp { timestamp_nanos: 0 },
+                    },
+                    timestamp: Timestamp { timestamp_nanos: 0 },
+                },
+                notification_id: 0,
+            }),
+            attempts: 0,
+        }
+    }
 }
@@ -0,0 +1,40 @@
+use candid::Principal;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry::Occupied;
+use std::collections::{HashMap, HashSet};
+use transaction_notifier::Role;
+
+// Roles granted to principals other than the admins, who implicitly hold every role
+#[derive(Serialize, Deserialize, Default)]
+pub struct Roles {
+    roles: HashMap<Principal, HashSet<Role>>,
+}
+
+impl Roles {
+    pub fn has(&self, principal: &Principal, role: Role) -> bool {
+        self.roles
+            .get(principal)
+            .map_or(false, |r| r.contains(&role))
+    }
+
+    pub fn grant(&mut self, principal: Principal, role: Role) -> bool {
+        self.roles.entry(principal).or_default().insert(role)
+    }
+
+    pub fn revoke(&mut self, principal: Principal, role: Role) -> bool {
+        if let Occupied(mut e) = self.roles.entry(principal) {
+            let roles = e.get_mut();
+            let removed = roles.remove(&role);
+            if roles.is_empty() {
+                e.remove();
+            }
+            removed
+        } else {
+            false
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Principal, &HashSet<Role>)> {
+        self.roles.iter()
+    }
+}
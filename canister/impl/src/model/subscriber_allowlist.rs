@@ -0,0 +1,39 @@
+use candid::Principal;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry::Occupied;
+use std::collections::{HashMap, HashSet};
+use types::CanisterId;
+
+// Canisters which an admin has allowed a principal to manage subscriptions for, in addition to the
+// principal itself and any canisters it controls.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SubscriberAllowlist {
+    allowlist: HashMap<Principal, HashSet<CanisterId>>,
+}
+
+impl SubscriberAllowlist {
+    pub fn is_allowed(&self, principal: &Principal, canister_id: &CanisterId) -> bool {
+        self.allowlist
+            .get(principal)
+            .map_or(false, |c| c.contains(canister_id))
+    }
+
+    pub fn add(&mut self, principal: Principal, canister_ids: Vec<CanisterId>) {
+        let allowed = self.allowlist.entry(principal).or_default();
+        for canister_id in canister_ids {
+            allowed.insert(canister_id);
+        }
+    }
+
+    pub fn remove(&mut self, principal: Principal, canister_ids: Vec<CanisterId>) {
+        if let Occupied(mut e) = self.allowlist.entry(principal) {
+            let allowed = e.get_mut();
+            for canister_id in canister_ids {
+                allowed.remove(&canister_id);
+            }
+            if allowed.is_empty() {
+                e.remove();
+            }
+        }
+    }
+}
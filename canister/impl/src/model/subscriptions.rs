@@ -1,26 +1,555 @@
+use crate::memory::{
+    deserialize, get_subscriptions_by_canister_memory, get_subscriptions_memory, serialize, Memory,
+};
+use candid::Principal;
 use ic_ledger_types::AccountIdentifier;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use transaction_notifier::subscriptions::SubscriptionDetails;
+use transaction_notifier::{
+    Account, Direction, NotificationFilter, OperationKind, SubscriptionAccount,
+};
 use types::CanisterId;
 
-#[derive(Serialize, Deserialize, Default)]
+// Keys are fixed length so that ordering them by their bytes groups together all of the entries for
+// a given account (or canister), allowing them to be read using a single range query.
+const ACCOUNT_KEY_LEN: usize = 64;
+pub const CANISTER_KEY_LEN: usize = 30;
+const SUBSCRIPTION_KEY_LEN: usize = ACCOUNT_KEY_LEN + CANISTER_KEY_LEN;
+
+type AccountKey = [u8; ACCOUNT_KEY_LEN];
+pub type CanisterKey = [u8; CANISTER_KEY_LEN];
+// The account key followed by the canister key
+type SubscriptionKey = [u8; SUBSCRIPTION_KEY_LEN];
+// The canister key followed by the account key
+type CanisterIndexKey = [u8; SUBSCRIPTION_KEY_LEN];
+
+// Held in stable memory so that subscriptions don't need to be serialized during upgrades
 pub struct Subscriptions {
-    subscriptions: HashMap<AccountIdentifier, HashSet<CanisterId>>,
+    subscriptions: StableBTreeMap<SubscriptionKey, SubscriptionEntry, Memory>,
+    // Reverse index from each subscribed canister to the accounts it is subscribed to
+    by_canister: StableBTreeMap<CanisterIndexKey, (), Memory>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SubscriptionEntry {
+    account: SubscriptionAccount,
+    canister_id: CanisterId,
+    filter: Option<NotificationFilter>,
+}
+
+impl Storable for SubscriptionEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(serialize(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        deserialize(&bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// The details of a transaction which are needed to evaluate the subscribers' filters
+pub struct TransactionSummary<'a> {
+    pub token_symbol: &'a str,
+    pub kind: OperationKind,
+    pub amount: u128,
 }
 
 impl Subscriptions {
-    pub fn get(&self, account_identifier: &AccountIdentifier) -> Option<&HashSet<CanisterId>> {
-        self.subscriptions.get(account_identifier)
+    pub fn add(
+        &mut self,
+        account_identifier: AccountIdentifier,
+        canister_ids: Vec<CanisterId>,
+        filter: Option<NotificationFilter>,
+    ) {
+        self.add_subscription(account_identifier.into(), canister_ids, filter);
     }
 
-    pub fn add(&mut self, account_identifier: AccountIdentifier, canister_ids: Vec<CanisterId>) {
-        let canisters_subscribed = self.subscriptions.entry(account_identifier).or_default();
-        for canister_id in canister_ids {
-            canisters_subscribed.insert(canister_id);
+    pub fn add_icrc(
+        &mut self,
+        account: Account,
+        canister_ids: Vec<CanisterId>,
+        filter: Option<NotificationFilter>,
+    ) {
+        self.add_subscription(account.into(), canister_ids, filter);
+    }
+
+    // Returns the canister ids which were actually removed
+    pub fn remove(
+        &mut self,
+        account_identifier: &AccountIdentifier,
+        canister_ids: Vec<CanisterId>,
+    ) -> Vec<CanisterId> {
+        self.remove_subscription(&(*account_identifier).into(), canister_ids)
+    }
+
+    pub fn remove_icrc(
+        &mut self,
+        account: &Account,
+        canister_ids: Vec<CanisterId>,
+    ) -> Vec<CanisterId> {
+        self.remove_subscription(&(*account).into(), canister_ids)
+    }
+
+    // Canisters to notify of a transaction on a ledger using the legacy ICP interface. Only
+    // subscriptions by account identifier match, since ICRC subscribers are sent notifications
+    // shaped for ICRC ledgers. Each account identifier is given along with the direction in which
+    // the funds moved relative to it.
+    pub fn canisters_to_notify(
+        &self,
+        account_identifiers: &[(AccountIdentifier, Option<Direction>)],
+        transaction: &TransactionSummary,
+    ) -> HashSet<CanisterId> {
+        let mut canister_ids = HashSet::new();
+
+        for (account_identifier, direction) in account_identifiers {
+            self.collect_canisters_to_notify(
+                &account_key(&(*account_identifier).into()),
+                *direction,
+                transaction,
+                &mut canister_ids,
+            );
         }
+
+        canister_ids
     }
 
-    pub fn len(&self) -> usize {
+    // Canisters to notify of a transaction on an ICRC ledger. Only ICRC subscriptions match, since
+    // canisters subscribed by account identifier only implement the legacy notification method.
+    pub fn canisters_to_notify_icrc(
+        &self,
+        accounts: &[(Account, Option<Direction>)],
+        transaction: &TransactionSummary,
+    ) -> HashSet<CanisterId> {
+        let mut canister_ids = HashSet::new();
+
+        for (account, direction) in accounts {
+            self.collect_canisters_to_notify(
+                &account_key(&(*account).into()),
+                *direction,
+                transaction,
+                &mut canister_ids,
+            );
+        }
+
+        canister_ids
+    }
+
+    // Returns the canisters subscribed to the account, ordered by canister id
+    pub fn for_account(&self, account: &SubscriptionAccount) -> Vec<SubscriptionDetails> {
+        let mut results: Vec<_> = self
+            .subscriptions
+            .range(account_range(&account_key(account)))
+            .map(|(_, entry)| entry.into())
+            .collect();
+
+        results.sort_unstable_by_key(|s: &SubscriptionDetails| s.canister_id);
+        results
+    }
+
+    // Returns the accounts the canister is subscribed to, ordered by account
+    pub fn for_canister(&self, canister_id: &CanisterId) -> Vec<SubscriptionDetails> {
+        let canister_key = canister_key(canister_id);
+
+        let mut results: Vec<SubscriptionDetails> = self
+            .by_canister
+            .range(canister_range(&canister_key))
+            .filter_map(|(key, _)| {
+                let mut account_key = [0; ACCOUNT_KEY_LEN];
+                account_key.copy_from_slice(&key[CANISTER_KEY_LEN..]);
+                self.subscriptions
+                    .get(&subscription_key(&account_key, &canister_key))
+            })
+            .map(|entry| entry.into())
+            .collect();
+
+        results.sort_unstable_by_key(|s| s.account);
+        results
+    }
+
+    // Returns the number of (account, canister) subscriptions
+    pub fn len(&self) -> u64 {
         self.subscriptions.len()
     }
+
+    fn add_subscription(
+        &mut self,
+        account: SubscriptionAccount,
+        canister_ids: Vec<CanisterId>,
+        filter: Option<NotificationFilter>,
+    ) {
+        let account_key = account_key(&account);
+        let filter = filter.filter(|f| *f != NotificationFilter::default());
+
+        for canister_id in canister_ids {
+            let canister_key = canister_key(&canister_id);
+
+            // Subscribing again replaces any existing filter
+            self.subscriptions.insert(
+                subscription_key(&account_key, &canister_key),
+                SubscriptionEntry {
+                    account,
+                    canister_id,
+                    filter: filter.clone(),
+                },
+            );
+            self.by_canister
+                .insert(canister_index_key(&canister_key, &account_key), ());
+        }
+    }
+
+    fn remove_subscription(
+        &mut self,
+        account: &SubscriptionAccount,
+        canister_ids: Vec<CanisterId>,
+    ) -> Vec<CanisterId> {
+        let account_key = account_key(account);
+        let mut removed = Vec::new();
+
+        for canister_id in canister_ids {
+            let canister_key = canister_key(&canister_id);
+
+            if self
+                .subscriptions
+                .remove(&subscription_key(&account_key, &canister_key))
+                .is_some()
+            {
+                self.by_canister
+                    .remove(&canister_index_key(&canister_key, &account_key));
+                removed.push(canister_id);
+            }
+        }
+
+        removed
+    }
+
+    fn collect_canisters_to_notify(
+        &self,
+        account_key: &AccountKey,
+        direction: Option<Direction>,
+        transaction: &TransactionSummary,
+        canister_ids: &mut HashSet<CanisterId>,
+    ) {
+        for (_, entry) in self.subscriptions.range(account_range(account_key)) {
+            let is_match = entry
+                .filter
+                .as_ref()
+                .map_or(true, |f| filter_matches(f, direction, transaction));
+
+            if is_match {
+                canister_ids.insert(entry.canister_id);
+            }
+        }
+    }
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Subscriptions {
+            subscriptions: StableBTreeMap::init(get_subscriptions_memory()),
+            by_canister: StableBTreeMap::init(get_subscriptions_by_canister_memory()),
+        }
+    }
+}
+
+impl From<SubscriptionEntry> for SubscriptionDetails {
+    fn from(entry: SubscriptionEntry) -> Self {
+        SubscriptionDetails {
+            account: entry.account,
+            canister_id: entry.canister_id,
+            filter: entry.filter,
+        }
+    }
+}
+
+// The layout in which subscriptions were held on the heap (and serialized during upgrades) before
+// they were moved into stable memory
+#[derive(Deserialize)]
+pub struct SubscriptionsPreviousVersion {
+    subscriptions: HashMap<AccountIdentifier, HashSet<CanisterId>>,
+    #[serde(default)]
+    icrc_subscriptions: HashMap<Account, HashSet<CanisterId>>,
+    #[serde(default)]
+    #[allow(dead_code)]
+    icrc_account_identifiers: HashMap<AccountIdentifier, Account>,
+    #[serde(default)]
+    filters: HashMap<AccountIdentifier, HashMap<CanisterId, NotificationFilter>>,
+    #[serde(default)]
+    icrc_filters: HashMap<Account, HashMap<CanisterId, NotificationFilter>>,
+}
+
+impl From<SubscriptionsPreviousVersion> for Subscriptions {
+    fn from(previous: SubscriptionsPreviousVersion) -> Self {
+        let mut subscriptions = Subscriptions::default();
+
+        for (account_identifier, canister_ids) in previous.subscriptions {
+            let filters = previous.filters.get(&account_identifier);
+            for canister_id in canister_ids {
+                let filter = filters.and_then(|f| f.get(&canister_id)).cloned();
+                subscriptions.add(account_identifier, vec![canister_id], filter);
+            }
+        }
+        for (account, canister_ids) in previous.icrc_subscriptions {
+            let filters = previous.icrc_filters.get(&account);
+            for canister_id in canister_ids {
+                let filter = filters.and_then(|f| f.get(&canister_id)).cloned();
+                subscriptions.add_icrc(account, vec![canister_id], filter);
+            }
+        }
+
+        subscriptions
+    }
+}
+
+fn account_key(account: &SubscriptionAccount) -> AccountKey {
+    let mut key = [0; ACCOUNT_KEY_LEN];
+
+    match account {
+        SubscriptionAccount::AccountIdentifier(account_identifier) => {
+            key[1..33].copy_from_slice(account_identifier.as_ref());
+        }
+        SubscriptionAccount::Icrc(account) => {
+            key[0] = 1;
+            write_principal(&mut key[1..31], &account.owner);
+            if let Some(subaccount) = account.subaccount {
+                key[31] = 1;
+                key[32..].copy_from_slice(&subaccount);
+            }
+        }
+    }
+    key
+}
+
+pub fn canister_key(canister_id: &CanisterId) -> CanisterKey {
+    let mut key = [0; CANISTER_KEY_LEN];
+    write_principal(&mut key, canister_id);
+    key
+}
+
+// Principals are at most 29 bytes so are written as a length byte followed by the padded bytes
+fn write_principal(buffer: &mut [u8], principal: &Principal) {
+    let bytes = principal.as_slice();
+    buffer[0] = bytes.len() as u8;
+    buffer[1..=bytes.len()].copy_from_slice(bytes);
+}
+
+fn subscription_key(account_key: &AccountKey, canister_key: &CanisterKey) -> SubscriptionKey {
+    let mut key = [0; SUBSCRIPTION_KEY_LEN];
+    key[..ACCOUNT_KEY_LEN].copy_from_slice(account_key);
+    key[ACCOUNT_KEY_LEN..].copy_from_slice(canister_key);
+    key
+}
+
+fn canister_index_key(canister_key: &CanisterKey, account_key: &AccountKey) -> CanisterIndexKey {
+    let mut key = [0; SUBSCRIPTION_KEY_LEN];
+    key[..CANISTER_KEY_LEN].copy_from_slice(canister_key);
+    key[CANISTER_KEY_LEN..].copy_from_slice(account_key);
+    key
+}
+
+fn account_range(account_key: &AccountKey) -> std::ops::RangeInclusive<SubscriptionKey> {
+    subscription_key(account_key, &[0; CANISTER_KEY_LEN])
+        ..=subscription_key(account_key, &[u8::MAX; CANISTER_KEY_LEN])
+}
+
+fn canister_range(canister_key: &CanisterKey) -> std::ops::RangeInclusive<CanisterIndexKey> {
+    canister_index_key(canister_key, &[0; ACCOUNT_KEY_LEN])
+        ..=canister_index_key(canister_key, &[u8::MAX; ACCOUNT_KEY_LEN])
+}
+
+fn filter_matches(
+    filter: &NotificationFilter,
+    direction: Option<Direction>,
+    transaction: &TransactionSummary,
+) -> bool {
+    filter.direction.map_or(true, |d| direction == Some(d))
+        && filter
+            .operation_kinds
+            .as_ref()
+            .map_or(true, |k| k.contains(&transaction.kind))
+        && filter.min_amount.map_or(true, |a| transaction.amount >= a)
+        && filter
+            .token_symbol
+            .as_ref()
+            .map_or(true, |s| s == transaction.token_symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_ledger_types::{Subaccount, DEFAULT_SUBACCOUNT};
+
+    #[test]
+    fn each_ledger_interface_only_notifies_its_own_subscribers() {
+        let mut subscriptions = Subscriptions::default();
+        let account = icrc_account(1);
+        let account_identifier = to_account_identifier(&account);
+        let icp_subscriber = Principal::from_slice(&[10]);
+        let icrc_subscriber = Principal::from_slice(&[11]);
+
+        subscriptions.add(account_identifier, vec![icp_subscriber], None);
+        subscriptions.add_icrc(account, vec![icrc_subscriber], None);
+
+        let canister_ids = subscriptions
+            .canisters_to_notify_icrc(&[(account, Some(Direction::Incoming))], &summary());
+        assert_eq!(canister_ids, HashSet::from([icrc_subscriber]));
+
+        let canister_ids = subscriptions.canisters_to_notify(
+            &[(account_identifier, Some(Direction::Incoming))],
+            &summary(),
+        );
+        assert_eq!(canister_ids, HashSet::from([icp_subscriber]));
+    }
+
+    #[test]
+    fn keys_of_different_accounts_and_canisters_are_distinct() {
+        let short = Principal::from_slice(&[1]);
+        let long = Principal::from_slice(&[1, 0]);
+        assert_ne!(canister_key(&short), canister_key(&long));
+
+        let with_subaccount = Account {
+            owner: short,
+            subaccount: Some([0; 32]),
+        };
+        assert_ne!(
+            account_key(&icrc_account(1).into()),
+            account_key(&with_subaccount.into())
+        );
+        assert_ne!(
+            account_key(&icrc_account(1).into()),
+            account_key(&to_account_identifier(&icrc_account(1)).into())
+        );
+    }
+
+    #[test]
+    fn subscriptions_are_indexed_by_account_and_by_canister() {
+        let mut subscriptions = Subscriptions::default();
+        let canister_1 = Principal::from_slice(&[10]);
+        let canister_2 = Principal::from_slice(&[10, 0]);
+
+        subscriptions.add_icrc(icrc_account(1), vec![canister_1, canister_2], None);
+        subscriptions.add_icrc(icrc_account(2), vec![canister_1], None);
+        assert_eq!(subscriptions.len(), 3);
+
+        let accounts: Vec<_> = subscriptions
+            .for_canister(&canister_1)
+            .into_iter()
+            .map(|s| s.account)
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![icrc_account(1).into(), icrc_account(2).into()]
+        );
+        assert_eq!(subscriptions.for_canister(&canister_2).len(), 1);
+        assert_eq!(subscriptions.for_account(&icrc_account(1).into()).len(), 2);
+
+        // Only the canisters which were subscribed are returned as removed
+        let removed = subscriptions.remove_icrc(
+            &icrc_account(1),
+            vec![canister_1, Principal::from_slice(&[12])],
+        );
+        assert_eq!(removed, vec![canister_1]);
+        assert_eq!(subscriptions.for_canister(&canister_1).len(), 1);
+        assert_eq!(subscriptions.len(), 2);
+    }
+
+    #[test]
+    fn filters_are_applied_per_subscription() {
+        let mut subscriptions = Subscriptions::default();
+        let account = icrc_account(1);
+        let unfiltered = Principal::from_slice(&[10]);
+        let filtered = Principal::from_slice(&[11]);
+
+        subscriptions.add_icrc(
+            account,
+            vec![unfiltered],
+            Some(NotificationFilter::default()),
+        );
+        subscriptions.add_icrc(
+            account,
+            vec![filtered],
+            Some(NotificationFilter {
+                direction: Some(Direction::Outgoing),
+                ..Default::default()
+            }),
+        );
+
+        // An empty filter is held as no filter
+        assert_eq!(subscriptions.for_account(&account.into())[0].filter, None);
+
+        let notify = |direction| {
+            subscriptions.canisters_to_notify_icrc(&[(account, Some(direction))], &summary())
+        };
+        assert_eq!(notify(Direction::Incoming), HashSet::from([unfiltered]));
+        assert_eq!(
+            notify(Direction::Outgoing),
+            HashSet::from([unfiltered, filtered])
+        );
+    }
+
+    #[test]
+    fn each_filter_field_must_be_satisfied() {
+        let transaction = summary();
+        let direction = Some(Direction::Incoming);
+        let matches = |filter: NotificationFilter| filter_matches(&filter, direction, &transaction);
+
+        assert!(matches(NotificationFilter::default()));
+        assert!(matches(NotificationFilter {
+            direction: Some(Direction::Incoming),
+            operation_kinds: Some(vec![OperationKind::Mint, OperationKind::Transfer]),
+            min_amount: Some(100),
+            token_symbol: Some("TKN".to_string()),
+        }));
+        assert!(!matches(NotificationFilter {
+            direction: Some(Direction::Outgoing),
+            ..Default::default()
+        }));
+        assert!(!matches(NotificationFilter {
+            operation_kinds: Some(vec![OperationKind::Burn]),
+            ..Default::default()
+        }));
+        assert!(!matches(NotificationFilter {
+            min_amount: Some(101),
+            ..Default::default()
+        }));
+        assert!(!matches(NotificationFilter {
+            token_symbol: Some("ICP".to_string()),
+            ..Default::default()
+        }));
+        // A transaction with no direction only matches filters without one
+        assert!(!filter_matches(
+            &NotificationFilter {
+                direction: Some(Direction::Incoming),
+                ..Default::default()
+            },
+            None,
+            &transaction
+        ));
+    }
+
+    fn to_account_identifier(account: &Account) -> AccountIdentifier {
+        let subaccount = account.subaccount.map_or(DEFAULT_SUBACCOUNT, Subaccount);
+
+        AccountIdentifier::new(&account.owner, &subaccount)
+    }
+
+    fn icrc_account(owner: u8) -> Account {
+        Account {
+            owner: Principal::from_slice(&[owner]),
+            subaccount: None,
+        }
+    }
+
+    fn summary() -> TransactionSummary<'static> {
+        TransactionSummary {
+            token_symbol: "TKN",
+            kind: OperationKind::Transfer,
+            amount: 100,
+        }
+    }
 }
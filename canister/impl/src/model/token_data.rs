@@ -1,25 +1,36 @@
+use crate::memory::{deserialize, serialize};
+use crate::model::ledger_sync_state::Version;
 use crate::{LedgerSyncState, TokenMetrics};
 use ic_ledger_types::BlockIndex;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
 use serde::{Deserialize, Serialize};
-use types::CanisterId;
+use std::borrow::Cow;
+use transaction_notifier::LedgerStandard;
+use types::{CanisterId, TimestampMillis};
 
 #[derive(Serialize, Deserialize)]
 pub struct TokenData {
     token_symbol: String,
     ledger_canister_id: CanisterId,
     ledger_sync_state: LedgerSyncState,
+    #[serde(default)]
+    ledger_standard: LedgerStandard,
 }
 
 impl TokenData {
     pub fn new(
         token_symbol: String,
         ledger_canister_id: CanisterId,
+        ledger_standard: LedgerStandard,
         sync_from_block_index: BlockIndex,
+        sync_version: Version,
     ) -> TokenData {
         TokenData {
             token_symbol,
             ledger_canister_id,
-            ledger_sync_state: LedgerSyncState::new(sync_from_block_index),
+            ledger_sync_state: LedgerSyncState::new(sync_from_block_index, sync_version),
+            ledger_standard,
         }
     }
 
@@ -31,19 +42,57 @@ impl TokenData {
         self.ledger_canister_id
     }
 
+    pub fn ledger_standard(&self) -> LedgerStandard {
+        self.ledger_standard
+    }
+
+    pub fn ledger_sync_state(&self) -> &LedgerSyncState {
+        &self.ledger_sync_state
+    }
+
     pub fn ledger_sync_state_mut(&mut self) -> &mut LedgerSyncState {
         &mut self.ledger_sync_state
     }
 
-    pub fn metrics(&self) -> TokenMetrics {
+    // Once fully synced the token is no longer behind, however old the last block is
+    pub fn metrics(&self, now: TimestampMillis) -> TokenMetrics {
+        let blocks_behind = self.ledger_sync_state.blocks_behind();
+        let seconds_behind = if blocks_behind == Some(0) {
+            Some(0)
+        } else {
+            self.ledger_sync_state
+                .last_block_timestamp()
+                .map(|ts| now.saturating_sub(ts) / 1000)
+        };
+
         TokenMetrics {
             token_symbol: self.token_symbol.clone(),
             ledger_canister_id: self.ledger_canister_id,
+            ledger_standard: self.ledger_standard,
             sync_enabled: self.ledger_sync_state.enabled(),
             synced_up_to: self.ledger_sync_state.next_block_to_sync().checked_sub(1),
             last_sync_started_at: self.ledger_sync_state.last_sync_started_at(),
             last_successful_sync: self.ledger_sync_state.last_successful_sync(),
             last_failed_sync: self.ledger_sync_state.last_failed_sync(),
+            hash_chain_mismatch: self.ledger_sync_state.hash_chain_mismatch(),
+            sync_interval: self.ledger_sync_state.sync_interval(),
+            sync_delay: self.ledger_sync_state.sync_delay(),
+            chain_length: self.ledger_sync_state.chain_length(),
+            last_block_timestamp: self.ledger_sync_state.last_block_timestamp(),
+            blocks_behind,
+            seconds_behind,
         }
     }
 }
+
+impl Storable for TokenData {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(serialize(self))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        deserialize(&bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
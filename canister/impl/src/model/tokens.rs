@@ -0,0 +1,258 @@
+use crate::memory::{get_tokens_by_symbol_memory, get_tokens_memory, Memory};
+use crate::model::ledger_sync_state::Version;
+use crate::model::subscriptions::{canister_key, CanisterKey};
+use crate::model::token_data::TokenData;
+use ic_stable_structures::StableBTreeMap;
+use std::cmp::max;
+use std::collections::{BTreeSet, HashMap};
+use types::CanisterId;
+
+// Held in stable memory, keyed by ledger canister id, so that each token's sync state doesn't need
+// to be serialized during upgrades. Tokens are read out of the map by value, so any changes must be
+// made via `update` in order for them to be written back.
+pub struct Tokens {
+    tokens: StableBTreeMap<CanisterKey, TokenData, Memory>,
+    // The sync version which newly added tokens start from. This is moved past the version of each
+    // token removed, so that a sync which was in progress when a token was removed can't be taken
+    // for a sync of the same ledger once it is added again. Syncs in progress don't survive an
+    // upgrade, so this doesn't need to be persisted.
+    next_sync_version: Version,
+}
+
+pub enum ResolveTokenError {
+    NotFound,
+    AmbiguousSymbol(Vec<CanisterId>),
+}
+
+impl Tokens {
+    pub fn get(&self, ledger_canister_id: CanisterId) -> Option<TokenData> {
+        self.tokens.get(&canister_key(&ledger_canister_id))
+    }
+
+    pub fn contains(&self, ledger_canister_id: CanisterId) -> bool {
+        self.tokens.contains_key(&canister_key(&ledger_canister_id))
+    }
+
+    pub fn insert(&mut self, token: TokenData) {
+        self.tokens
+            .insert(canister_key(&token.ledger_canister_id()), token);
+    }
+
+    pub fn remove(&mut self, ledger_canister_id: CanisterId) -> Option<TokenData> {
+        let token = self.tokens.remove(&canister_key(&ledger_canister_id))?;
+        self.next_sync_version = max(
+            self.next_sync_version,
+            token.ledger_sync_state().version() + 1,
+        );
+        Some(token)
+    }
+
+    pub fn next_sync_version(&self) -> Version {
+        self.next_sync_version
+    }
+
+    // Whether a sync started at `version` is still current, ie. the token hasn't been removed nor
+    // had its sync position reset since
+    pub fn is_current_sync(&self, ledger_canister_id: CanisterId, version: Version) -> bool {
+        self.get(ledger_canister_id)
+            .map_or(false, |t| t.ledger_sync_state().version() == version)
+    }
+
+    pub fn update<F: FnOnce(&mut TokenData) -> R, R>(
+        &mut self,
+        ledger_canister_id: CanisterId,
+        f: F,
+    ) -> Option<R> {
+        let mut token = self.get(ledger_canister_id)?;
+        let result = f(&mut token);
+        self.insert(token);
+        Some(result)
+    }
+
+    // Finds the ledger of the token identified by either its ledger canister id or its symbol.
+    // Symbols are not unique, so looking a token up by symbol fails if several tokens share it.
+    pub fn resolve(
+        &self,
+        ledger_canister_id: Option<CanisterId>,
+        token_symbol: Option<&str>,
+    ) -> Result<CanisterId, ResolveTokenError> {
+        if let Some(ledger_canister_id) = ledger_canister_id {
+            return if self.contains(ledger_canister_id) {
+                Ok(ledger_canister_id)
+            } else {
+                Err(ResolveTokenError::NotFound)
+            };
+        }
+
+        let matches: Vec<_> = self
+            .iter()
+            .filter(|t| Some(t.token_symbol()) == token_symbol)
+            .map(|t| t.ledger_canister_id())
+            .collect();
+
+        match matches.as_slice() {
+            [] => Err(ResolveTokenError::NotFound),
+            [ledger_canister_id] => Ok(*ledger_canister_id),
+            _ => Err(ResolveTokenError::AmbiguousSymbol(matches)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TokenData> + '_ {
+        self.tokens.iter().map(|(_, token)| token)
+    }
+
+    // Symbols are not unique, so each is only returned once
+    pub fn symbols(&self) -> Vec<String> {
+        let symbols: BTreeSet<_> = self.iter().map(|t| t.token_symbol().to_string()).collect();
+        symbols.into_iter().collect()
+    }
+
+    // One-time migration of the tokens which were previously keyed by symbol
+    pub fn migrate_from_symbol_keys(&mut self) {
+        let mut tokens_by_symbol: StableBTreeMap<String, TokenData, Memory> =
+            StableBTreeMap::init(get_tokens_by_symbol_memory());
+
+        let symbols: Vec<_> = tokens_by_symbol.iter().map(|(symbol, _)| symbol).collect();
+        for symbol in symbols {
+            if let Some(token) = tokens_by_symbol.remove(&symbol) {
+                self.insert(token);
+            }
+        }
+    }
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Tokens {
+            tokens: StableBTreeMap::init(get_tokens_memory()),
+            next_sync_version: 0,
+        }
+    }
+}
+
+impl From<HashMap<String, TokenData>> for Tokens {
+    fn from(previous: HashMap<String, TokenData>) -> Self {
+        let mut tokens = Tokens::default();
+        for token in previous.into_values() {
+            tokens.insert(token);
+        }
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ledger_sync_state::TryStartSyncResult;
+    use candid::Principal;
+    use transaction_notifier::LedgerStandard;
+
+    #[test]
+    fn token_is_resolved_by_ledger_canister_id() {
+        let tokens = tokens();
+
+        assert!(matches!(tokens.resolve(Some(ledger(1)), None), Ok(id) if id == ledger(1)));
+        // The ledger canister id takes precedence over the symbol
+        assert!(matches!(
+            tokens.resolve(Some(ledger(3)), Some("CKBTC")),
+            Ok(id) if id == ledger(3)
+        ));
+        assert!(matches!(
+            tokens.resolve(Some(ledger(4)), Some("ICP")),
+            Err(ResolveTokenError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn token_is_resolved_by_unique_symbol() {
+        let tokens = tokens();
+
+        assert!(matches!(tokens.resolve(None, Some("ICP")), Ok(id) if id == ledger(1)));
+        assert!(matches!(
+            tokens.resolve(None, Some("XYZ")),
+            Err(ResolveTokenError::NotFound)
+        ));
+        assert!(matches!(
+            tokens.resolve(None, None),
+            Err(ResolveTokenError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn shared_symbol_is_ambiguous() {
+        let tokens = tokens();
+
+        match tokens.resolve(None, Some("CKBTC")) {
+            Err(ResolveTokenError::AmbiguousSymbol(mut ids)) => {
+                ids.sort();
+                assert_eq!(ids, vec![ledger(2), ledger(3)]);
+            }
+            _ => panic!("expected the symbol to be ambiguous"),
+        }
+    }
+
+    #[test]
+    fn shared_symbol_is_only_listed_once() {
+        assert_eq!(tokens().symbols(), vec!["CKBTC", "ICP"]);
+    }
+
+    #[test]
+    fn sync_is_not_current_once_token_is_removed_and_added_again() {
+        let mut tokens = tokens();
+        let version = start_sync(&mut tokens, ledger(1));
+
+        tokens.remove(ledger(1));
+        assert!(!tokens.is_current_sync(ledger(1), version));
+
+        tokens.insert(TokenData::new(
+            "ICP".to_string(),
+            ledger(1),
+            LedgerStandard::Icrc1,
+            0,
+            tokens.next_sync_version(),
+        ));
+        assert!(!tokens.is_current_sync(ledger(1), version));
+        assert!(tokens.is_current_sync(ledger(1), start_sync(&mut tokens, ledger(1))));
+    }
+
+    #[test]
+    fn sync_is_not_current_once_sync_position_is_reset() {
+        let mut tokens = tokens();
+        let version = start_sync(&mut tokens, ledger(2));
+        assert!(tokens.is_current_sync(ledger(2), version));
+
+        tokens.update(ledger(2), |t| t.ledger_sync_state_mut().reset(10));
+        assert!(!tokens.is_current_sync(ledger(2), version));
+    }
+
+    fn start_sync(tokens: &mut Tokens, ledger_canister_id: CanisterId) -> Version {
+        tokens
+            .update(ledger_canister_id, |t| {
+                let ledger_sync_state = t.ledger_sync_state_mut();
+                ledger_sync_state.set_enabled(true);
+                match ledger_sync_state.try_start(0) {
+                    TryStartSyncResult::Success(_, version) => version,
+                    _ => panic!("expected the sync to start"),
+                }
+            })
+            .unwrap()
+    }
+
+    fn tokens() -> Tokens {
+        let mut tokens = Tokens::default();
+        for (symbol, id) in [("ICP", 1), ("CKBTC", 2), ("CKBTC", 3)] {
+            tokens.insert(TokenData::new(
+                symbol.to_string(),
+                ledger(id),
+                LedgerStandard::Icrc1,
+                0,
+                0,
+            ));
+        }
+        tokens
+    }
+
+    fn ledger(id: u8) -> CanisterId {
+        Principal::from_slice(&[id])
+    }
+}
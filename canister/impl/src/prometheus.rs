@@ -0,0 +1,297 @@
+use crate::{Metrics, TokenMetrics};
+use std::fmt::{Display, Write};
+use types::TimestampMillis;
+
+const PREFIX: &str = "transaction_notifier";
+
+// Renders the metrics in the Prometheus text exposition format. Per token metrics are labelled with
+// the token symbol and ledger canister id, since symbols alone are not unique.
+pub fn encode(metrics: &Metrics) -> String {
+    let mut encoder = Encoder::default();
+
+    encoder.gauge(
+        "cycles_balance",
+        "The canister's cycles balance",
+        metrics.cycles_balance,
+    );
+    encoder.gauge(
+        "memory_used_bytes",
+        "The memory used by the canister",
+        metrics.memory_used,
+    );
+    encoder.labelled_gauge(
+        "memory_bytes",
+        "The memory used by the canister, broken down by region",
+        "region",
+        &[
+            ("heap", metrics.memory.heap),
+            ("stable", metrics.memory.stable),
+            ("upgrades", metrics.memory.upgrades),
+            ("tokens", metrics.memory.tokens),
+            ("subscriptions", metrics.memory.subscriptions),
+            ("notification_queue", metrics.memory.notification_queue),
+            ("audit_log", metrics.memory.audit_log),
+            ("log_messages", metrics.memory.log_messages),
+        ],
+    );
+    encoder.gauge(
+        "subscriptions",
+        "The number of (account, canister) subscriptions",
+        metrics.subscriptions,
+    );
+    encoder.counter(
+        "notifications_sent_total",
+        "The number of notifications pushed successfully",
+        metrics.notifications_sent,
+    );
+    encoder.counter(
+        "notifications_failed_total",
+        "The number of failed notification push attempts",
+        metrics.notifications_failed,
+    );
+    encoder.counter(
+        "notifications_retried_total",
+        "The number of times a notification was scheduled for retry after failing",
+        metrics.notifications_retried,
+    );
+    encoder.gauge(
+        "notifications_queued",
+        "The number of notifications waiting to be pushed",
+        metrics.notifications_queued,
+    );
+    encoder.gauge(
+        "notifications_pending_retry",
+        "The number of notifications waiting to be retried",
+        metrics.notifications_pending_retry,
+    );
+    encoder.gauge(
+        "notifications_pending_pull",
+        "The number of notifications waiting to be pulled",
+        metrics.notifications_pending_pull,
+    );
+    encoder.gauge(
+        "dead_letters",
+        "The number of notifications which exhausted their push attempts",
+        metrics.dead_letters,
+    );
+    encoder.gauge(
+        "failing_subscribers",
+        "The number of subscribers whose latest push attempt failed",
+        metrics.failing_subscribers,
+    );
+
+    encoder.token_gauge(
+        "token_sync_enabled",
+        "Whether syncing is enabled for the token",
+        &metrics.tokens,
+        |t| Some(u8::from(t.sync_enabled)),
+    );
+    encoder.token_gauge(
+        "token_synced_up_to",
+        "The index of the last block synced",
+        &metrics.tokens,
+        |t| t.synced_up_to,
+    );
+    encoder.token_gauge(
+        "token_sync_lag_seconds",
+        "The time since the last successful sync",
+        &metrics.tokens,
+        |t| {
+            (t.last_successful_sync > 0)
+                .then(|| to_seconds(metrics.now.saturating_sub(t.last_successful_sync)))
+        },
+    );
+    encoder.token_gauge(
+        "token_last_successful_sync_timestamp_seconds",
+        "The time of the last successful sync",
+        &metrics.tokens,
+        |t| (t.last_successful_sync > 0).then(|| to_seconds(t.last_successful_sync)),
+    );
+    encoder.token_gauge(
+        "token_last_failed_sync_timestamp_seconds",
+        "The time of the last failed sync",
+        &metrics.tokens,
+        |t| (t.last_failed_sync > 0).then(|| to_seconds(t.last_failed_sync)),
+    );
+    encoder.token_gauge(
+        "token_chain_length",
+        "The ledger's chain length as of the last successful sync",
+        &metrics.tokens,
+        |t| t.chain_length,
+    );
+    encoder.token_gauge(
+        "token_blocks_behind",
+        "The number of blocks on the ledger which have yet to be synced",
+        &metrics.tokens,
+        |t| t.blocks_behind,
+    );
+    encoder.token_gauge(
+        "token_seconds_behind",
+        "The age of the last block synced, or 0 if there are no more blocks to sync",
+        &metrics.tokens,
+        |t| t.seconds_behind,
+    );
+    encoder.token_gauge(
+        "token_hash_chain_mismatch",
+        "Whether syncing has been halted because the block hash chain didn't link up",
+        &metrics.tokens,
+        |t| Some(u8::from(t.hash_chain_mismatch.is_some())),
+    );
+
+    encoder.output
+}
+
+#[derive(Default)]
+struct Encoder {
+    output: String,
+}
+
+impl Encoder {
+    fn gauge<V: Display>(&mut self, name: &str, help: &str, value: V) {
+        self.header(name, help, "gauge");
+        self.sample(name, &[], value);
+    }
+
+    fn counter<V: Display>(&mut self, name: &str, help: &str, value: V) {
+        self.header(name, help, "counter");
+        self.sample(name, &[], value);
+    }
+
+    fn labelled_gauge<V: Display>(
+        &mut self,
+        name: &str,
+        help: &str,
+        label: &str,
+        values: &[(&str, V)],
+    ) {
+        self.header(name, help, "gauge");
+        for (label_value, value) in values {
+            self.sample(name, &[(label, *label_value)], value);
+        }
+    }
+
+    // Tokens for which `value` returns None have no sample
+    fn token_gauge<V: Display, F: Fn(&TokenMetrics) -> Option<V>>(
+        &mut self,
+        name: &str,
+        help: &str,
+        tokens: &[TokenMetrics],
+        value: F,
+    ) {
+        self.header(name, help, "gauge");
+        for token in tokens {
+            if let Some(value) = value(token) {
+                let ledger_canister_id = token.ledger_canister_id.to_string();
+                self.sample(
+                    name,
+                    &[
+                        ("token_symbol", &token.token_symbol),
+                        ("ledger_canister_id", &ledger_canister_id),
+                    ],
+                    value,
+                );
+            }
+        }
+    }
+
+    fn header(&mut self, name: &str, help: &str, metric_type: &str) {
+        writeln!(self.output, "# HELP {PREFIX}_{name} {help}").unwrap();
+        writeln!(self.output, "# TYPE {PREFIX}_{name} {metric_type}").unwrap();
+    }
+
+    fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        write!(self.output, "{PREFIX}_{name}").unwrap();
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(self.output, "{{{labels}}}").unwrap();
+        }
+        writeln!(self.output, " {value}").unwrap();
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn to_seconds(timestamp: TimestampMillis) -> f64 {
+    timestamp as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    #[test]
+    fn metrics_have_help_and_type_lines() {
+        let output = encode(&Metrics {
+            cycles_balance: 1_000,
+            notifications_sent: 5,
+            ..Default::default()
+        });
+
+        assert!(output.contains(
+            "# HELP transaction_notifier_cycles_balance The canister's cycles balance\n\
+             # TYPE transaction_notifier_cycles_balance gauge\n\
+             transaction_notifier_cycles_balance 1000\n"
+        ));
+        assert!(output.contains(
+            "# TYPE transaction_notifier_notifications_sent_total counter\n\
+             transaction_notifier_notifications_sent_total 5\n"
+        ));
+        assert!(output.contains("transaction_notifier_memory_bytes{region=\"heap\"} 0\n"));
+    }
+
+    #[test]
+    fn token_metrics_are_labelled_and_escaped() {
+        let output = encode(&Metrics {
+            now: 12_500,
+            tokens: vec![token("A\"B", 2_500)],
+            ..Default::default()
+        });
+        let labels = format!(
+            "token_symbol=\"A\\\"B\",ledger_canister_id=\"{}\"",
+            Principal::from_slice(&[1])
+        );
+
+        assert!(output.contains(&format!(
+            "transaction_notifier_token_sync_lag_seconds{{{labels}}} 10\n"
+        )));
+        assert!(output.contains(&format!(
+            "transaction_notifier_token_last_successful_sync_timestamp_seconds{{{labels}}} 2.5\n"
+        )));
+    }
+
+    #[test]
+    fn missing_token_values_have_no_sample() {
+        let output = encode(&Metrics {
+            tokens: vec![token("ICP", 0)],
+            ..Default::default()
+        });
+
+        assert!(output.contains("# TYPE transaction_notifier_token_sync_lag_seconds gauge\n"));
+        assert!(!output.contains("transaction_notifier_token_sync_lag_seconds{"));
+        assert!(!output.contains("transaction_notifier_token_chain_length{"));
+        assert!(output.contains("transaction_notifier_token_sync_enabled{"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label_value("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+
+    fn token(token_symbol: &str, last_successful_sync: TimestampMillis) -> TokenMetrics {
+        TokenMetrics {
+            last_sync_started_at: last_successful_sync,
+            last_successful_sync,
+            ..TokenMetrics::test_default(token_symbol)
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use crate::guards::caller_is_admin;
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::admins::{Response::*, *};
+
+#[query(guard = "caller_is_admin")]
+#[trace]
+fn admins(_args: Args) -> Response {
+    read_state(admins_impl)
+}
+
+fn admins_impl(state: &State) -> Response {
+    let mut admins: Vec<_> = state.data.admins.iter().copied().collect();
+    admins.sort();
+    Success(admins)
+}
@@ -0,0 +1,31 @@
+use crate::guards::caller_is_observer;
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::audit_log::{Response::*, *};
+
+const MAX_RESULTS_LIMIT: u32 = 100;
+
+#[query(guard = "caller_is_observer")]
+#[trace]
+fn audit_log(args: Args) -> Response {
+    read_state(|state| audit_log_impl(args, state))
+}
+
+fn audit_log_impl(args: Args, state: &State) -> Response {
+    let max_results = args.max_results.min(MAX_RESULTS_LIMIT) as usize;
+    let from_id = args.after_id.map_or(0, |id| id + 1);
+
+    let entries = state
+        .data
+        .audit_log
+        .iter_from(from_id)
+        .take(max_results)
+        .map(|e| e.into())
+        .collect();
+
+    Success(SuccessResult {
+        entries,
+        total: state.data.audit_log.len(),
+    })
+}
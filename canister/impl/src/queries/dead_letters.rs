@@ -0,0 +1,29 @@
+use crate::guards::caller_is_observer;
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::dead_letters::{Response::*, *};
+
+const MAX_RESULTS_LIMIT: u32 = 100;
+
+#[query(guard = "caller_is_observer")]
+#[trace]
+fn dead_letters(args: Args) -> Response {
+    read_state(|state| dead_letters_impl(args, state))
+}
+
+fn dead_letters_impl(args: Args, state: &State) -> Response {
+    let max_results = args.max_results.min(MAX_RESULTS_LIMIT) as usize;
+
+    let dead_letters = state
+        .data
+        .notifications
+        .dead_letters()
+        .filter(|d| args.after_id.map_or(true, |id| d.id > id))
+        .filter(|d| d.matches(&args.filter))
+        .take(max_results)
+        .map(|d| d.into())
+        .collect();
+
+    Success(dead_letters)
+}
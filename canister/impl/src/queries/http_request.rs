@@ -1,38 +1,103 @@
-use crate::read_state;
+use crate::{health, prometheus, read_state, LOG_MESSAGES};
 use candid::CandidType;
 use canister_tracing_macros::trace;
 use ic_cdk_macros::query;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
+use types::TimestampMillis;
 
 #[query]
 #[trace]
 fn http_request(request: HttpRequest) -> HttpResponse {
-    let path = request
+    let (path, query) = request
         .url
+        .split_once('?')
+        .unwrap_or((request.url.as_str(), ""));
+
+    let path = path
         .trim_start_matches('/')
         .trim_end_matches('/')
         .to_lowercase();
 
     match path.as_str() {
-        "metrics" => {
-            let metrics = read_state(|state| state.metrics());
-
-            let body = serde_json::to_string(&metrics).unwrap().into_bytes();
-
-            HttpResponse {
-                status_code: 200,
-                headers: vec![
-                    HeaderField("Content-Type".to_string(), "application/json".to_string()),
-                    HeaderField("Content-Length".to_string(), body.len().to_string()),
-                ],
-                body: ByteBuf::from(body),
-            }
+        "health" => health_response(),
+        "metrics" if accepts_prometheus(&request) => prometheus_response(),
+        "metrics" => to_json_response(&read_state(|state| state.metrics())),
+        "metrics/prometheus" => prometheus_response(),
+        // HTTP requests can't be authenticated, so logs and traces are only served over HTTP in
+        // test mode. Otherwise they are available via the observer guarded queries.
+        "logs" if is_test_mode() => {
+            to_json_response(&LOG_MESSAGES.with(|l| l.borrow().logs.get(since(query))))
+        }
+        "traces" if is_test_mode() => {
+            to_json_response(&LOG_MESSAGES.with(|l| l.borrow().traces.get(since(query))))
         }
         _ => HttpResponse::not_found(),
     }
 }
 
+fn is_test_mode() -> bool {
+    read_state(|state| state.data.test_mode)
+}
+
+// Parses the `since` parameter from the query string, defaulting to 0 if it is missing or invalid
+fn since(query: &str) -> TimestampMillis {
+    query
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| *k == "since")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or_default()
+}
+
+// Prometheus requests the text format via the Accept header when scraping
+fn accepts_prometheus(request: &HttpRequest) -> bool {
+    request
+        .headers
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case("accept") && v.contains("text/plain"))
+}
+
+fn prometheus_response() -> HttpResponse {
+    let body = read_state(|state| prometheus::encode(&state.metrics())).into_bytes();
+
+    HttpResponse {
+        status_code: 200,
+        headers: vec![
+            HeaderField(
+                "Content-Type".to_string(),
+                "text/plain; version=0.0.4".to_string(),
+            ),
+            HeaderField("Content-Length".to_string(), body.len().to_string()),
+        ],
+        body: ByteBuf::from(body),
+    }
+}
+
+// Returns 503 if any of the health checks fail so that uptime monitors only need the status code
+fn health_response() -> HttpResponse {
+    let report = read_state(|state| health::check(&state.metrics(), &state.data.health_thresholds));
+    let status_code = if report.healthy { 200 } else { 503 };
+
+    HttpResponse {
+        status_code,
+        ..to_json_response(&report)
+    }
+}
+
+fn to_json_response<T: Serialize>(data: &T) -> HttpResponse {
+    let body = serde_json::to_string(data).unwrap().into_bytes();
+
+    HttpResponse {
+        status_code: 200,
+        headers: vec![
+            HeaderField("Content-Type".to_string(), "application/json".to_string()),
+            HeaderField("Content-Length".to_string(), body.len().to_string()),
+        ],
+        body: ByteBuf::from(body),
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct HeaderField(pub String, pub String);
 
@@ -0,0 +1,29 @@
+use crate::guards::caller_is_observer;
+use crate::LOG_MESSAGES;
+use canister_logger::LogMessagesContainer;
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::logs::{Response::*, *};
+use transaction_notifier::LogMessage;
+use types::TimestampMillis;
+
+#[query(guard = "caller_is_observer")]
+#[trace]
+fn logs(args: Args) -> Response {
+    Success(LOG_MESSAGES.with(|l| messages_since(&l.borrow().logs, args.since)))
+}
+
+// Shared with the `traces` query
+pub fn messages_since(
+    container: &LogMessagesContainer,
+    since: Option<TimestampMillis>,
+) -> Vec<LogMessage> {
+    container
+        .get(since.unwrap_or_default())
+        .into_iter()
+        .map(|m| LogMessage {
+            timestamp: m.timestamp,
+            json: m.json,
+        })
+        .collect()
+}
@@ -1,2 +1,10 @@
+mod admins;
+mod audit_log;
+mod dead_letters;
 mod http_request;
+mod logs;
+mod roles;
+mod subscriber_stats;
+mod subscriptions;
 mod supported_tokens;
+mod traces;
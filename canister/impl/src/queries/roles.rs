@@ -0,0 +1,27 @@
+use crate::guards::caller_is_admin;
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use itertools::Itertools;
+use transaction_notifier::roles::{Response::*, *};
+
+#[query(guard = "caller_is_admin")]
+#[trace]
+fn roles(_args: Args) -> Response {
+    read_state(roles_impl)
+}
+
+fn roles_impl(state: &State) -> Response {
+    let mut assignments: Vec<_> = state
+        .data
+        .roles
+        .iter()
+        .map(|(principal, roles)| RoleAssignment {
+            principal: *principal,
+            roles: roles.iter().copied().sorted().collect(),
+        })
+        .collect();
+
+    assignments.sort_by_key(|a| a.principal);
+    Success(assignments)
+}
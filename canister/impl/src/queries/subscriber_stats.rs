@@ -0,0 +1,26 @@
+use crate::guards::caller_is_observer;
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::subscriber_stats::{Response::*, *};
+
+#[query(guard = "caller_is_observer")]
+#[trace]
+fn subscriber_stats(args: Args) -> Response {
+    read_state(|state| subscriber_stats_impl(args, state))
+}
+
+fn subscriber_stats_impl(args: Args, state: &State) -> Response {
+    let notifications = &state.data.notifications;
+
+    let stats = if args.canister_ids.is_empty() {
+        notifications.all_subscriber_stats()
+    } else {
+        args.canister_ids
+            .iter()
+            .filter_map(|c| notifications.subscriber_stats(c))
+            .collect()
+    };
+
+    Success(stats)
+}
@@ -0,0 +1,57 @@
+use crate::{read_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::subscriptions::{Response::*, *};
+use transaction_notifier::Role;
+use types::CanisterId;
+
+const MAX_RESULTS_LIMIT: u32 = 100;
+
+#[query]
+#[trace]
+fn subscriptions(args: Args) -> Response {
+    read_state(|state| subscriptions_impl(args, state))
+}
+
+fn subscriptions_impl(args: Args, state: &State) -> Response {
+    let caller = state.env.caller();
+    let can_view_all = state.data.has_role(&caller, Role::SubscriptionManager)
+        || state.data.has_role(&caller, Role::Observer);
+
+    // Other callers can only see the subscriptions of canisters which they are permitted to manage
+    let can_view = |canister_id: &CanisterId| {
+        can_view_all
+            || *canister_id == caller
+            || state
+                .data
+                .subscriber_allowlist
+                .is_allowed(&caller, canister_id)
+    };
+
+    let subscriptions = match args.query {
+        SubscriptionsQuery::Account(account) => state.data.subscriptions.for_account(&account),
+        SubscriptionsQuery::Canister(canister_id) => {
+            if !can_view(&canister_id) {
+                return NotAuthorized;
+            }
+            state.data.subscriptions.for_canister(&canister_id)
+        }
+    };
+
+    let visible: Vec<_> = subscriptions
+        .into_iter()
+        .filter(|s| can_view(&s.canister_id))
+        .collect();
+
+    let total = visible.len() as u32;
+    let max_results = args.max_results.min(MAX_RESULTS_LIMIT) as usize;
+
+    Success(SuccessResult {
+        subscriptions: visible
+            .into_iter()
+            .skip(args.skip as usize)
+            .take(max_results)
+            .collect(),
+        total,
+    })
+}
@@ -10,6 +10,6 @@ fn supported_tokens(_args: Args) -> Response {
 }
 
 fn supported_tokens_impl(state: &State) -> Response {
-    let tokens = state.data.tokens.keys().cloned().collect();
+    let tokens = state.data.tokens.symbols();
     Success(tokens)
 }
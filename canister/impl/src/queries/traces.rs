@@ -0,0 +1,12 @@
+use crate::guards::caller_is_observer;
+use crate::queries::logs::messages_since;
+use crate::LOG_MESSAGES;
+use canister_tracing_macros::trace;
+use ic_cdk_macros::query;
+use transaction_notifier::traces::{Response::*, *};
+
+#[query(guard = "caller_is_observer")]
+#[trace]
+fn traces(args: Args) -> Response {
+    Success(LOG_MESSAGES.with(|l| messages_since(&l.borrow().traces, args.since)))
+}
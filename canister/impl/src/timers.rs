@@ -0,0 +1,159 @@
+use crate::jobs::{push_notifications, sync_ledger_transactions};
+use crate::read_state;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use types::{CanisterId, TimestampMillis};
+
+const NANOS_PER_MILLISECOND: u64 = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Job {
+    SyncToken(CanisterId),
+    PushNotifications,
+}
+
+// Jobs are run off the canister's global timer, which is always set to the time of the earliest
+// job. Each job is held at most once, so scheduling a job which is already due to run sooner has
+// no effect.
+#[derive(Default)]
+struct Timers {
+    queue: BTreeSet<(TimestampMillis, Job)>,
+    scheduled: HashMap<Job, TimestampMillis>,
+}
+
+thread_local! {
+    static TIMERS: RefCell<Timers> = RefCell::default();
+}
+
+pub fn schedule(job: Job, at: TimestampMillis) {
+    TIMERS.with(|t| {
+        let mut timers = t.borrow_mut();
+
+        if timers.schedule(job, at) {
+            set_global_timer(timers.next_run_at());
+        }
+    });
+}
+
+#[export_name = "canister_global_timer"]
+extern "C" fn global_timer() {
+    ic_cdk::setup();
+
+    let now = read_state(|state| state.env.now());
+    let due_jobs = TIMERS.with(|t| t.borrow_mut().take_due(now));
+
+    for job in due_jobs {
+        match job {
+            Job::SyncToken(ledger_canister_id) => sync_ledger_transactions::run(ledger_canister_id),
+            Job::PushNotifications => push_notifications::run(),
+        }
+    }
+
+    TIMERS.with(|t| set_global_timer(t.borrow().next_run_at()));
+}
+
+impl Timers {
+    // Returns false if the job is already due to run at or before the given time
+    fn schedule(&mut self, job: Job, at: TimestampMillis) -> bool {
+        if let Some(&existing) = self.scheduled.get(&job) {
+            if existing <= at {
+                return false;
+            }
+            self.queue.remove(&(existing, job.clone()));
+        }
+        self.scheduled.insert(job.clone(), at);
+        self.queue.insert((at, job));
+        true
+    }
+
+    fn take_due(&mut self, now: TimestampMillis) -> Vec<Job> {
+        let mut due = Vec::new();
+        while let Some((at, _)) = self.queue.first() {
+            if *at > now {
+                break;
+            }
+            let (_, job) = self.queue.pop_first().unwrap();
+            self.scheduled.remove(&job);
+            due.push(job);
+        }
+        due
+    }
+
+    fn next_run_at(&self) -> Option<TimestampMillis> {
+        self.queue.first().map(|(at, _)| *at)
+    }
+}
+
+// Setting the timer to 0 deactivates it
+fn set_global_timer(at: Option<TimestampMillis>) {
+    let timestamp_nanos = at.map_or(0, |ts| ts.max(1).saturating_mul(NANOS_PER_MILLISECOND));
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        #[link(wasm_import_module = "ic0")]
+        extern "C" {
+            fn global_timer_set(timestamp: i64) -> i64;
+        }
+
+        unsafe {
+            global_timer_set(timestamp_nanos as i64);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = timestamp_nanos;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    #[test]
+    fn due_jobs_are_taken_in_order() {
+        let mut timers = Timers::default();
+        timers.schedule(sync_token(1), 30);
+        timers.schedule(Job::PushNotifications, 10);
+        timers.schedule(sync_token(2), 20);
+
+        assert_eq!(timers.next_run_at(), Some(10));
+        assert_eq!(
+            timers.take_due(20),
+            vec![Job::PushNotifications, sync_token(2)]
+        );
+        assert_eq!(timers.next_run_at(), Some(30));
+        assert!(timers.take_due(29).is_empty());
+        assert_eq!(timers.take_due(30), vec![sync_token(1)]);
+        assert_eq!(timers.next_run_at(), None);
+    }
+
+    #[test]
+    fn job_is_only_moved_earlier() {
+        let mut timers = Timers::default();
+        assert!(timers.schedule(Job::PushNotifications, 20));
+
+        assert!(!timers.schedule(Job::PushNotifications, 30));
+        assert!(!timers.schedule(Job::PushNotifications, 20));
+        assert_eq!(timers.next_run_at(), Some(20));
+
+        assert!(timers.schedule(Job::PushNotifications, 10));
+        assert_eq!(timers.next_run_at(), Some(10));
+        assert_eq!(timers.queue.len(), 1);
+        assert_eq!(timers.take_due(10), vec![Job::PushNotifications]);
+        assert!(timers.take_due(20).is_empty());
+    }
+
+    #[test]
+    fn job_can_be_rescheduled_once_taken() {
+        let mut timers = Timers::default();
+        timers.schedule(Job::PushNotifications, 10);
+        timers.take_due(10);
+
+        assert!(timers.schedule(Job::PushNotifications, 50));
+        assert_eq!(timers.next_run_at(), Some(50));
+    }
+
+    fn sync_token(id: u8) -> Job {
+        Job::SyncToken(Principal::from_slice(&[id]))
+    }
+}
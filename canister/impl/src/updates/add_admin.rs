@@ -0,0 +1,21 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use tracing::info;
+use transaction_notifier::add_admin::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn add_admin(args: Args) -> Response {
+    mutate_state(|state| state.audited("add_admin", args, add_admin_impl))
+}
+
+fn add_admin_impl(args: Args, state: &mut State) -> Response {
+    if state.data.admins.insert(args.principal) {
+        info!(principal = %args.principal, "Admin added");
+        Success
+    } else {
+        AlreadyAdmin
+    }
+}
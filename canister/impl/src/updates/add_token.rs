@@ -1,28 +1,35 @@
-use crate::guards::caller_is_admin;
-use crate::{mutate_state, read_state, State, TokenData};
+use crate::guards::caller_is_token_operator;
+use crate::timers::{schedule, Job};
+use crate::{icrc_ledger, mutate_state, read_state, State, TokenData};
 use canister_tracing_macros::trace;
 use ic_cdk::api::call::CallResult;
 use ic_cdk_macros::update;
 use ic_ledger_types::{BlockIndex, GetBlocksArgs};
-use std::collections::hash_map::Entry::Vacant;
 use transaction_notifier::add_token::{Response::*, *};
+use transaction_notifier::LedgerStandard;
 use types::CanisterId;
 
-#[update(guard = "caller_is_admin")]
+#[update(guard = "caller_is_token_operator")]
 #[trace]
 async fn add_token(args: Args) -> Response {
-    if read_state(|state| {
-        state
-            .data
-            .tokens
-            .values()
-            .any(|t| t.ledger_canister_id() == args.ledger_canister_id)
-    }) {
+    let args_text = format!("{args:?}");
+    let response = try_add_token(args).await;
+
+    mutate_state(|state| state.record_audit("add_token", args_text, &response));
+    response
+}
+
+async fn try_add_token(args: Args) -> Response {
+    if read_state(|state| state.data.tokens.contains(args.ledger_canister_id)) {
         AlreadyAdded
     } else {
-        let token_symbol_future = token_symbol(args.ledger_canister_id);
-        let block_index_future =
-            sync_from_block_index(args.ledger_canister_id, args.sync_from_block_index);
+        let ledger_standard = args.ledger_standard.unwrap_or_default();
+        let token_symbol_future = token_symbol(args.ledger_canister_id, ledger_standard);
+        let block_index_future = sync_from_block_index(
+            args.ledger_canister_id,
+            ledger_standard,
+            args.sync_from_block_index,
+        );
 
         let (token_symbol_res, block_index_res) =
             futures::future::join(token_symbol_future, block_index_future).await;
@@ -32,6 +39,7 @@ async fn add_token(args: Args) -> Response {
                 add_token_impl(
                     token_symbol,
                     args.ledger_canister_id,
+                    ledger_standard,
                     block_index,
                     args.enable_sync,
                     state,
@@ -45,38 +53,52 @@ async fn add_token(args: Args) -> Response {
 fn add_token_impl(
     token_symbol: String,
     ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
     sync_from_block_index: BlockIndex,
     enable_sync: bool,
     state: &mut State,
 ) -> Response {
-    match state.data.tokens.entry(token_symbol.clone()) {
-        Vacant(e) => {
-            let token_data = e.insert(TokenData::new(
-                token_symbol,
-                ledger_canister_id,
-                sync_from_block_index,
-            ));
-            if enable_sync {
-                token_data.ledger_sync_state_mut().set_enabled(true);
-            }
-            Success
+    if state.data.tokens.contains(ledger_canister_id) {
+        AlreadyAdded
+    } else {
+        let mut token_data = TokenData::new(
+            token_symbol,
+            ledger_canister_id,
+            ledger_standard,
+            sync_from_block_index,
+            state.data.tokens.next_sync_version(),
+        );
+        if enable_sync {
+            token_data.ledger_sync_state_mut().set_enabled(true);
+            schedule(Job::SyncToken(ledger_canister_id), state.env.now());
         }
-        _ => AlreadyAdded,
+        state.data.tokens.insert(token_data);
+        Success
     }
 }
 
-async fn token_symbol(ledger_canister_id: CanisterId) -> CallResult<String> {
-    ic_ledger_types::token_symbol(ledger_canister_id)
-        .await
-        .map(|res| res.symbol)
+async fn token_symbol(
+    ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
+) -> CallResult<String> {
+    if ledger_standard == LedgerStandard::Icp {
+        ic_ledger_types::token_symbol(ledger_canister_id)
+            .await
+            .map(|res| res.symbol)
+    } else {
+        icrc_ledger::token_symbol(ledger_canister_id).await
+    }
 }
 
 async fn sync_from_block_index(
     ledger_canister_id: CanisterId,
+    ledger_standard: LedgerStandard,
     block_index_override: Option<BlockIndex>,
 ) -> CallResult<BlockIndex> {
     if let Some(block_index) = block_index_override {
         Ok(block_index)
+    } else if ledger_standard != LedgerStandard::Icp {
+        icrc_ledger::chain_length(ledger_canister_id, ledger_standard).await
     } else {
         ic_ledger_types::query_blocks(
             ledger_canister_id,
@@ -0,0 +1,27 @@
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::get_notifications::{Response::*, *};
+
+const MAX_RESULTS_LIMIT: u32 = 100;
+
+// This is an update rather than a query so that the acknowledged notifications can be removed
+#[update]
+#[trace]
+fn get_notifications(args: Args) -> Response {
+    mutate_state(|state| get_notifications_impl(args, state))
+}
+
+fn get_notifications_impl(args: Args, state: &mut State) -> Response {
+    let caller = state.env.caller();
+    let max_results = args.max_results.min(MAX_RESULTS_LIMIT) as usize;
+
+    match state
+        .data
+        .notifications
+        .pull(&caller, args.since_id, max_results)
+    {
+        Some(notifications) => Success(SuccessResult { notifications }),
+        None => PullModeNotEnabled,
+    }
+}
@@ -1,3 +1,15 @@
+mod add_admin;
 mod add_token;
+mod get_notifications;
+mod grant_role;
+mod purge_dead_letters;
+mod remove_admin;
+mod remove_token;
+mod replay_dead_letters;
+mod revoke_role;
+mod set_delivery_mode;
+mod set_health_thresholds;
 mod subscribe;
+mod unsubscribe;
+mod update_subscriber_allowlist;
 mod update_token_config;
@@ -0,0 +1,20 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::purge_dead_letters::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn purge_dead_letters(args: Args) -> Response {
+    mutate_state(|state| state.audited("purge_dead_letters", args, purge_dead_letters_impl))
+}
+
+fn purge_dead_letters_impl(args: Args, state: &mut State) -> Response {
+    let count = state
+        .data
+        .notifications
+        .purge_dead_letters(|d| d.is_selected(&args.selection));
+
+    Success(count as u32)
+}
@@ -0,0 +1,24 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use tracing::info;
+use transaction_notifier::remove_admin::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn remove_admin(args: Args) -> Response {
+    mutate_state(|state| state.audited("remove_admin", args, remove_admin_impl))
+}
+
+fn remove_admin_impl(args: Args, state: &mut State) -> Response {
+    if !state.data.admins.contains(&args.principal) {
+        NotAdmin
+    } else if state.data.admins.len() == 1 {
+        CannotRemoveLastAdmin
+    } else {
+        state.data.admins.remove(&args.principal);
+        info!(principal = %args.principal, "Admin removed");
+        Success
+    }
+}
@@ -0,0 +1,44 @@
+use crate::guards::caller_is_token_operator;
+use crate::model::tokens::ResolveTokenError;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use tracing::info;
+use transaction_notifier::remove_token::{Response::*, *};
+
+#[update(guard = "caller_is_token_operator")]
+#[trace]
+fn remove_token(args: Args) -> Response {
+    mutate_state(|state| state.audited("remove_token", args, remove_token_impl))
+}
+
+// A sync which is in progress for the token completes without enqueuing any notifications or
+// recording its progress, even if the token has been added again in the meantime. Any sync which
+// is scheduled finds the token missing and doesn't start.
+fn remove_token_impl(args: Args, state: &mut State) -> Response {
+    let ledger_canister_id = match state
+        .data
+        .tokens
+        .resolve(args.ledger_canister_id, args.token_symbol.as_deref())
+    {
+        Ok(ledger_canister_id) => ledger_canister_id,
+        Err(ResolveTokenError::NotFound) => return TokenNotFound,
+        Err(ResolveTokenError::AmbiguousSymbol(ledgers)) => return AmbiguousTokenSymbol(ledgers),
+    };
+
+    let token = state.data.tokens.remove(ledger_canister_id).unwrap();
+
+    let purged = if args.purge_pending_notifications {
+        state.data.notifications.purge_ledger(ledger_canister_id)
+    } else {
+        0
+    };
+
+    info!(
+        token_symbol = token.token_symbol(),
+        %ledger_canister_id,
+        purged,
+        "Token removed"
+    );
+    Success(purged as u32)
+}
@@ -0,0 +1,26 @@
+use crate::guards::caller_is_admin;
+use crate::timers::{schedule, Job};
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::replay_dead_letters::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn replay_dead_letters(args: Args) -> Response {
+    mutate_state(|state| state.audited("replay_dead_letters", args, replay_dead_letters_impl))
+}
+
+fn replay_dead_letters_impl(args: Args, state: &mut State) -> Response {
+    let now = state.env.now();
+    let count = state
+        .data
+        .notifications
+        .replay_dead_letters(|d| d.is_selected(&args.selection), now);
+
+    if count > 0 {
+        schedule(Job::PushNotifications, now);
+    }
+
+    Success(count as u32)
+}
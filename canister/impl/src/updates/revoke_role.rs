@@ -0,0 +1,21 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use tracing::info;
+use transaction_notifier::revoke_role::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn revoke_role(args: Args) -> Response {
+    mutate_state(|state| state.audited("revoke_role", args, revoke_role_impl))
+}
+
+fn revoke_role_impl(args: Args, state: &mut State) -> Response {
+    if state.data.roles.revoke(args.principal, args.role) {
+        info!(principal = %args.principal, role = ?args.role, "Role revoked");
+        Success
+    } else {
+        NotGranted
+    }
+}
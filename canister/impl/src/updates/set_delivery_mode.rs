@@ -0,0 +1,40 @@
+use crate::authorization::canisters_caller_cannot_manage;
+use crate::timers::{schedule, Job};
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::set_delivery_mode::{Response::*, *};
+use transaction_notifier::DeliveryMode;
+
+#[update]
+#[trace]
+async fn set_delivery_mode(args: Args) -> Response {
+    let not_authorized = canisters_caller_cannot_manage(args.canister_ids.clone()).await;
+    if !not_authorized.is_empty() {
+        return NotAuthorized(not_authorized);
+    }
+
+    mutate_state(|state| {
+        let canister_ids = args.canister_ids.clone();
+        state.audited_unless_caller_is_subscriber(
+            "set_delivery_mode",
+            &canister_ids,
+            args,
+            set_delivery_mode_impl,
+        )
+    })
+}
+
+fn set_delivery_mode_impl(args: Args, state: &mut State) -> Response {
+    let now = state.env.now();
+
+    for canister_id in args.canister_ids {
+        match args.mode {
+            DeliveryMode::Push => state.data.notifications.disable_pull_mode(canister_id),
+            DeliveryMode::Pull => state.data.notifications.enable_pull_mode(canister_id, now),
+        }
+    }
+    // Any notifications held for pull are queued again when switching back to push
+    schedule(Job::PushNotifications, now);
+    Success
+}
@@ -0,0 +1,16 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::set_health_thresholds::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn set_health_thresholds(args: Args) -> Response {
+    mutate_state(|state| state.audited("set_health_thresholds", args, set_health_thresholds_impl))
+}
+
+fn set_health_thresholds_impl(args: Args, state: &mut State) -> Response {
+    state.data.health_thresholds = args.thresholds;
+    Success
+}
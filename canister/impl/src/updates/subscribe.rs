@@ -1,3 +1,4 @@
+use crate::authorization::canisters_caller_cannot_manage;
 use crate::{mutate_state, State};
 use canister_tracing_macros::trace;
 use ic_cdk_macros::update;
@@ -5,16 +6,44 @@ use transaction_notifier::subscribe::{Response::*, *};
 
 #[update]
 #[trace]
-fn subscribe(args: Args) -> Response {
-    mutate_state(|state| subscribe_impl(args, state))
+async fn subscribe(args: Args) -> Response {
+    let canister_ids: Vec<_> = args
+        .subscriptions
+        .iter()
+        .flat_map(|s| s.canister_ids.iter())
+        .chain(
+            args.icrc_subscriptions
+                .iter()
+                .flatten()
+                .flat_map(|s| s.canister_ids.iter()),
+        )
+        .copied()
+        .collect();
+
+    let not_authorized = canisters_caller_cannot_manage(canister_ids.clone()).await;
+    if !not_authorized.is_empty() {
+        return NotAuthorized(not_authorized);
+    }
+
+    mutate_state(|state| {
+        state.audited_unless_caller_is_subscriber("subscribe", &canister_ids, args, subscribe_impl)
+    })
 }
 
 fn subscribe_impl(args: Args, state: &mut State) -> Response {
     for subscription in args.subscriptions {
-        state
-            .data
-            .subscriptions
-            .add(subscription.account_identifier, subscription.canister_ids);
+        state.data.subscriptions.add(
+            subscription.account_identifier,
+            subscription.canister_ids,
+            subscription.filter,
+        );
+    }
+    for subscription in args.icrc_subscriptions.unwrap_or_default() {
+        state.data.subscriptions.add_icrc(
+            subscription.account,
+            subscription.canister_ids,
+            subscription.filter,
+        );
     }
     Success
 }
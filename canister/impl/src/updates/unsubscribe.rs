@@ -0,0 +1,75 @@
+use crate::authorization::canisters_caller_cannot_manage;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::unsubscribe::{Response::*, *};
+
+#[update]
+#[trace]
+async fn unsubscribe(args: Args) -> Response {
+    let canister_ids: Vec<_> = args
+        .subscriptions
+        .iter()
+        .flat_map(|s| s.canister_ids.iter())
+        .chain(
+            args.icrc_subscriptions
+                .iter()
+                .flatten()
+                .flat_map(|s| s.canister_ids.iter()),
+        )
+        .copied()
+        .collect();
+
+    let not_authorized = canisters_caller_cannot_manage(canister_ids.clone()).await;
+    if !not_authorized.is_empty() {
+        return NotAuthorized(not_authorized);
+    }
+
+    mutate_state(|state| {
+        state.audited_unless_caller_is_subscriber(
+            "unsubscribe",
+            &canister_ids,
+            args,
+            unsubscribe_impl,
+        )
+    })
+}
+
+fn unsubscribe_impl(args: Args, state: &mut State) -> Response {
+    let mut removed = Vec::new();
+
+    for subscription in args.subscriptions {
+        let canister_ids = state
+            .data
+            .subscriptions
+            .remove(&subscription.account_identifier, subscription.canister_ids);
+
+        if !canister_ids.is_empty() {
+            removed.push(Unsubscription {
+                account_identifier: subscription.account_identifier,
+                canister_ids,
+            });
+        }
+    }
+
+    let mut icrc_removed = Vec::new();
+
+    for subscription in args.icrc_subscriptions.unwrap_or_default() {
+        let canister_ids = state
+            .data
+            .subscriptions
+            .remove_icrc(&subscription.account, subscription.canister_ids);
+
+        if !canister_ids.is_empty() {
+            icrc_removed.push(IcrcUnsubscription {
+                account: subscription.account,
+                canister_ids,
+            });
+        }
+    }
+
+    Success(SuccessResult {
+        removed,
+        icrc_removed,
+    })
+}
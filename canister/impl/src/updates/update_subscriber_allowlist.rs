@@ -0,0 +1,24 @@
+use crate::guards::caller_is_admin;
+use crate::{mutate_state, State};
+use canister_tracing_macros::trace;
+use ic_cdk_macros::update;
+use transaction_notifier::update_subscriber_allowlist::{Response::*, *};
+
+#[update(guard = "caller_is_admin")]
+#[trace]
+fn update_subscriber_allowlist(args: Args) -> Response {
+    mutate_state(|state| {
+        state.audited(
+            "update_subscriber_allowlist",
+            args,
+            update_subscriber_allowlist_impl,
+        )
+    })
+}
+
+fn update_subscriber_allowlist_impl(args: Args, state: &mut State) -> Response {
+    let allowlist = &mut state.data.subscriber_allowlist;
+    allowlist.add(args.principal, args.add);
+    allowlist.remove(args.principal, args.remove);
+    Success
+}
@@ -1,27 +1,44 @@
-use crate::guards::caller_is_admin;
+use crate::guards::caller_is_token_operator;
+use crate::model::tokens::ResolveTokenError;
+use crate::timers::{schedule, Job};
 use crate::{mutate_state, State};
 use canister_tracing_macros::trace;
 use ic_cdk_macros::update;
 use transaction_notifier::update_token_config::{Response::*, *};
 
-#[update(guard = "caller_is_admin")]
+#[update(guard = "caller_is_token_operator")]
 #[trace]
 fn update_token_config(args: Args) -> Response {
-    mutate_state(|state| update_token_config_impl(args, state))
+    mutate_state(|state| state.audited("update_token_config", args, update_token_config_impl))
 }
 
 fn update_token_config_impl(args: Args, state: &mut State) -> Response {
-    if let Some(token) = state.data.tokens.get_mut(&args.token_symbol) {
+    let ledger_canister_id = match state
+        .data
+        .tokens
+        .resolve(args.ledger_canister_id, args.token_symbol.as_deref())
+    {
+        Ok(ledger_canister_id) => ledger_canister_id,
+        Err(ResolveTokenError::NotFound) => return TokenNotFound,
+        Err(ResolveTokenError::AmbiguousSymbol(ledgers)) => return AmbiguousTokenSymbol(ledgers),
+    };
+
+    let sync_enabled = state.data.tokens.update(ledger_canister_id, |token| {
         let ledger_sync_state = token.ledger_sync_state_mut();
         if let Some(enabled) = args.sync_enabled {
             ledger_sync_state.set_enabled(enabled);
         }
         if let Some(block_index) = args.sync_from_block_index {
-            ledger_sync_state.set_next_block_to_sync(block_index, None);
-            ledger_sync_state.incr_version();
+            ledger_sync_state.reset(block_index);
+        }
+        if let Some(sync_interval) = args.sync_interval {
+            ledger_sync_state.set_sync_interval(sync_interval);
         }
-        Success
-    } else {
-        TokenNotFound
+        ledger_sync_state.enabled()
+    });
+
+    if sync_enabled == Some(true) {
+        schedule(Job::SyncToken(ledger_canister_id), state.env.now());
     }
+    Success
 }
@@ -8,4 +8,6 @@ pub use version::*;
 
 pub type CanisterId = Principal;
 pub type Cycles = u128;
+pub type Milliseconds = u64;
 pub type TimestampMillis = u64;
+pub type TimestampNanos = u64;